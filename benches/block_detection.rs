@@ -0,0 +1,112 @@
+//! Measures how long it takes to set up an interpreter (decoding codels, detecting
+//! blocks and their exits) for generated images of growing size.
+//!
+//! Run with `cargo bench --bench block_detection`. The time per codel should stay
+//! roughly the same for every size, i.e. block detection scales linearly.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use rpiet::{Config, Interpreter, RgbRows};
+
+const SIZES: [usize; 4] = [250, 500, 1000, 2000];
+const ROUNDS: u32 = 3;
+
+type ImageGenerator = fn(usize) -> RgbRows;
+
+const PIET_COLORS: [(u8, u8, u8); 18] = [
+    (0xFF, 0xC0, 0xC0),
+    (0xFF, 0x00, 0x00),
+    (0xC0, 0x00, 0x00),
+    (0xFF, 0xFF, 0xC0),
+    (0xFF, 0xFF, 0x00),
+    (0xC0, 0xC0, 0x00),
+    (0xC0, 0xFF, 0xC0),
+    (0x00, 0xFF, 0x00),
+    (0x00, 0xC0, 0x00),
+    (0xC0, 0xFF, 0xFF),
+    (0x00, 0xFF, 0xFF),
+    (0x00, 0xC0, 0xC0),
+    (0xC0, 0xC0, 0xFF),
+    (0x00, 0x00, 0xFF),
+    (0x00, 0x00, 0xC0),
+    (0xFF, 0xC0, 0xFF),
+    (0xFF, 0x00, 0xFF),
+    (0xC0, 0x00, 0xC0),
+];
+
+// one color block covering the whole image
+fn single_block(size: usize) -> RgbRows {
+    vec![vec![PIET_COLORS[1]; size]; size]
+}
+
+// 4x4 tiles cycling through the colors, so every tile is a block of its own
+fn small_blocks(size: usize) -> RgbRows {
+    (0..size)
+        .map(|y| {
+            (0..size)
+                .map(|x| PIET_COLORS[(x / 4 + y / 4) % PIET_COLORS.len()])
+                .collect()
+        })
+        .collect()
+}
+
+// one pixel wide diagonal stripes, which give the flood fill long and thin blocks
+fn stripes(size: usize) -> RgbRows {
+    (0..size)
+        .map(|y| {
+            (0..size)
+                .map(|x| {
+                    if (x + y) % 2 == 0 {
+                        PIET_COLORS[(x + y) / 2 % PIET_COLORS.len()]
+                    } else {
+                        (0xFF, 0xFF, 0xFF)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn measure(rgb_rows: &RgbRows, config: &Config) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let rgb_rows = rgb_rows.clone();
+            let start = Instant::now();
+            let interpreter =
+                Interpreter::from_rgb_rows_with_io(rgb_rows, config, io::empty(), io::sink())
+                    .expect("generated images are valid");
+            let elapsed = start.elapsed();
+            assert!(!interpreter.blocks().is_empty());
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let config = Config::default();
+    let images: [(&str, ImageGenerator); 3] = [
+        ("single block", single_block),
+        ("small blocks", small_blocks),
+        ("stripes", stripes),
+    ];
+    println!(
+        "{:<14} {:>11} {:>12} {:>14}",
+        "image", "size", "time", "ns per codel"
+    );
+    for (name, generate) in images.iter() {
+        for &size in SIZES.iter() {
+            let rgb_rows = generate(size);
+            let elapsed = measure(&rgb_rows, &config);
+            let nanos_per_codel = elapsed.as_nanos() as f64 / (size * size) as f64;
+            println!(
+                "{:<14} {:>11} {:>10.1}ms {:>14.1}",
+                name,
+                format!("{}x{}", size, size),
+                elapsed.as_secs_f64() * 1000.0,
+                nanos_per_codel
+            );
+        }
+    }
+}
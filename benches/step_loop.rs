@@ -0,0 +1,88 @@
+//! Compares running the sample images step by step with and without precomputed
+//! transitions (`Config::precompute_transitions`).
+//!
+//! Run with `cargo bench --bench step_loop`. Every image runs for at most
+//! `MAX_STEPS` steps with empty input, setup times are listed separately.
+
+use std::fs::{self, File};
+use std::io;
+use std::time::{Duration, Instant};
+
+use rpiet::{Config, Interpreter, RgbRows};
+
+const MAX_STEPS: u128 = 200_000;
+const ROUNDS: u32 = 3;
+
+struct Measurement {
+    steps: u128,
+    setup: Duration,
+    run: Duration,
+}
+
+fn measure(rgb_rows: &RgbRows, config: &Config) -> Measurement {
+    (0..ROUNDS)
+        .map(|_| {
+            let rgb_rows = rgb_rows.clone();
+            let start = Instant::now();
+            let mut interpreter =
+                Interpreter::from_rgb_rows_with_io(rgb_rows, config, io::empty(), io::sink())
+                    .expect("sample images are valid");
+            let setup = start.elapsed();
+            let start = Instant::now();
+            while interpreter.is_alive() {
+                interpreter.advance();
+            }
+            Measurement {
+                steps: interpreter.step_counter(),
+                setup,
+                run: start.elapsed(),
+            }
+        })
+        .min_by_key(|measurement| measurement.setup + measurement.run)
+        .unwrap()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn main() {
+    let config = Config {
+        max_steps: MAX_STEPS,
+        unlimited_steps: false,
+        ..Config::default()
+    };
+    let precomputed_config = Config {
+        precompute_transitions: true,
+        ..config.clone()
+    };
+    let mut image_paths = fs::read_dir("sample_images")
+        .expect("run the benchmark from the repository root")
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    image_paths.sort();
+
+    println!(
+        "{:<24} {:>7} {:>10} {:>10} {:>10} {:>10} {:>8}",
+        "image", "steps", "setup", "run", "setup*", "run*", "speedup"
+    );
+    for path in image_paths.iter() {
+        let path = path.to_str().unwrap();
+        let file = File::open(path).unwrap();
+        let rgb_rows = rpiet::create_canvas(&file, path, &config).unwrap();
+        let plain = measure(&rgb_rows, &config);
+        let precomputed = measure(&rgb_rows, &precomputed_config);
+        assert_eq!(plain.steps, precomputed.steps);
+        println!(
+            "{:<24} {:>7} {:>8.2}ms {:>8.2}ms {:>8.2}ms {:>8.2}ms {:>7.1}x",
+            path.trim_start_matches("sample_images/"),
+            plain.steps,
+            millis(plain.setup),
+            millis(plain.run),
+            millis(precomputed.setup),
+            millis(precomputed.run),
+            plain.run.as_secs_f64() / precomputed.run.as_secs_f64()
+        );
+    }
+    println!("(* with precomputed transitions)");
+}
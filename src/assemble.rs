@@ -0,0 +1,487 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::command::Command;
+use crate::error::RpietError;
+use crate::palette::{Palette, PaletteColor};
+use crate::utils::RgbRows;
+
+// larger values are built from smaller pushes and arithmetic, which keeps push blocks small
+const MAX_PUSH_BLOCK_SIZE: u64 = 16;
+// push blocks hang down from the main row in columns of this height
+const PUSH_ROWS: usize = 4;
+// every jump routes through its own band of rows below the push blocks
+const JUMP_BAND_ROWS: usize = 8;
+// blocks which are not entered by a command (but by a slide through white) may have any color
+const FREE_COLOR: PaletteColor = PaletteColor::Color { hue: 0, light: 0 };
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Jump {
+    Always,
+    IfZero,
+    IfNotZero,
+}
+
+/// Lays out a program written in the rpiet assembly language as rows of Piet codels.
+///
+/// A program has one instruction per line, `#` and `;` start comments:
+///
+/// ```text
+///         push 5          # push a number or a character like 'A'
+/// loop:   dup             # labels end with a colon
+///         outn
+///         push 1
+///         sub
+///         dup
+///         jnz loop        # jump if the popped value is not zero
+///         halt
+/// ```
+///
+/// The commands are `push <N>`, `pop`, `add`, `sub`, `mul`, `div`, `mod`, `not`,
+/// `gt`, `dup`, `roll`, `inn`, `inc`, `outn` and `outc`. Control flow uses `jmp`,
+/// `jz` and `jnz` (which pop the value they test) instead of `pointer` and
+/// `switch`, and `halt`. A program ends after its last instruction.
+///
+/// The commands run from left to right along the top row. A jump is a POINTER
+/// command which turns down a white column to a route in its own band of rows,
+/// which leads to the column of its label and up again.
+pub fn assemble(source: &str) -> Result<RgbRows, RpietError> {
+    let mut layout = Layout::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| RpietError::InvalidAssembly {
+            line: line_number,
+            message,
+        };
+        let (mut word, mut argument) = split_first_word(strip_comment(line).trim());
+        while let Some(label) = word.strip_suffix(':') {
+            if !is_valid_label(label) {
+                return Err(error(format!("invalid label name `{}`", label)));
+            }
+            if !layout.label(label) {
+                return Err(error(format!("label `{}` is defined twice", label)));
+            }
+            let (next_word, next_argument) = split_first_word(argument);
+            word = next_word;
+            argument = next_argument;
+        }
+        if word.is_empty() {
+            continue;
+        }
+        let mnemonic = word.to_lowercase();
+        let argument = Some(argument).filter(|argument| !argument.is_empty());
+        let takes_argument = matches!(mnemonic.as_str(), "push" | "jmp" | "jz" | "jnz");
+        if let (false, Some(argument)) = (takes_argument, argument) {
+            return Err(error(format!(
+                "`{}` does not take an argument, got `{}`",
+                mnemonic, argument
+            )));
+        }
+        let expect_argument = |description: &str| {
+            argument.ok_or_else(|| error(format!("`{}` expects {}", mnemonic, description)))
+        };
+        match mnemonic.as_str() {
+            "push" => {
+                let value = expect_argument("a number or a character")?;
+                let value = parse_value(value)
+                    .ok_or_else(|| error(format!("invalid value `{}`", value)))?;
+                layout.push_value(value);
+            }
+            "jmp" => layout.jump(Jump::Always, expect_argument("a label")?, line_number),
+            "jz" => layout.jump(Jump::IfZero, expect_argument("a label")?, line_number),
+            "jnz" => layout.jump(Jump::IfNotZero, expect_argument("a label")?, line_number),
+            "halt" => layout.halt(),
+            "pointer" | "switch" => {
+                return Err(error(format!(
+                    "`{}` is used for jumps, use `jmp`, `jz` or `jnz` instead",
+                    mnemonic
+                )));
+            }
+            _ => {
+                let command = parse_command(&mnemonic)
+                    .ok_or_else(|| error(format!("unknown instruction `{}`", mnemonic)))?;
+                layout.command(command);
+            }
+        }
+    }
+    layout.halt();
+    layout.into_rgb_rows()
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '\'' => in_quotes = !in_quotes,
+            '#' | ';' if !in_quotes => return &line[..index],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn split_first_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+fn is_valid_label(label: &str) -> bool {
+    let mut chars = label.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+// numbers, or characters in single quotes which push their code point
+fn parse_value(text: &str) -> Option<i64> {
+    if let Some(quoted) = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        let c = match quoted {
+            "\\n" => '\n',
+            "\\t" => '\t',
+            "\\\\" => '\\',
+            "\\'" => '\'',
+            _ => {
+                let mut chars = quoted.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return None,
+                }
+            }
+        };
+        return Some(c as i64);
+    }
+    // i64::MIN has no positive counterpart to subtract from zero
+    text.parse::<i64>().ok().filter(|&value| value != i64::MIN)
+}
+
+fn parse_command(mnemonic: &str) -> Option<Command> {
+    match mnemonic {
+        "pop" => Some(Command::Pop),
+        "add" => Some(Command::Add),
+        "sub" => Some(Command::Subtract),
+        "mul" => Some(Command::Multiply),
+        "div" => Some(Command::Divide),
+        "mod" => Some(Command::Mod),
+        "not" => Some(Command::Not),
+        "gt" => Some(Command::Greater),
+        "dup" => Some(Command::Duplicate),
+        "roll" => Some(Command::Roll),
+        "inn" => Some(Command::InNumber),
+        "inc" => Some(Command::InChar),
+        "outn" => Some(Command::OutNumber),
+        "outc" => Some(Command::OutChar),
+        _ => None,
+    }
+}
+
+fn shift_color(color: PaletteColor, (light_diff, hue_diff): (u8, u8)) -> PaletteColor {
+    match color {
+        PaletteColor::Color { hue, light } => PaletteColor::Color {
+            hue: (hue + hue_diff) % 6,
+            light: (light + light_diff) % 3,
+        },
+        color => color,
+    }
+}
+
+// the color of the block which executes `command` when entered from a block of `color`
+fn next_color(color: PaletteColor, command: Command) -> PaletteColor {
+    shift_color(color, command.diffs())
+}
+
+// the color of the block which executes `command` when leaving it for a block of `color`
+fn previous_color(color: PaletteColor, command: Command) -> PaletteColor {
+    let (light_diff, hue_diff) = command.diffs();
+    shift_color(color, ((3 - light_diff) % 3, (6 - hue_diff) % 6))
+}
+
+/*
+ * A Layout places the program while it is parsed. Commands go from left to
+ * right along row 0 (the main row), with DP pointing right and CC left all the
+ * time. White codels in the main row separate blocks without executing a
+ * command, so the block after them may have any color.
+ *
+ * Labels become junctions: a block in the main row which is entered from the
+ * left through white, or from below by a POINTER command which turns DP from up
+ * to right. Jumps end in a branch block, entered by a POINTER command which
+ * turns DP down if the jump is taken. Below the branch a white column leads
+ * into the jump's band, where it pushes the turns for both corners of its route,
+ * turns towards the label's column, slides along a white row and turns up the
+ * label's white column into the junction.
+ */
+struct Layout {
+    cells: Vec<((usize, usize), PaletteColor)>,
+    // column of the next block in the main row
+    x: usize,
+    // color and size of the block the program is in, `None` after a halt
+    current: Option<(PaletteColor, usize)>,
+    labels: HashMap<String, usize>,
+    // branch column, label and source line of every jump
+    jumps: Vec<(usize, String, usize)>,
+    last_junction: Option<usize>,
+    last_branch: Option<usize>,
+}
+
+impl Layout {
+    fn new() -> Layout {
+        let mut layout = Layout {
+            cells: Vec::new(),
+            x: 0,
+            current: None,
+            labels: HashMap::new(),
+            jumps: Vec::new(),
+            last_junction: None,
+            last_branch: None,
+        };
+        // the program starts in the top left codel
+        layout.block(FREE_COLOR);
+        layout
+    }
+
+    fn is_previous_column(&self, column: Option<usize>) -> bool {
+        self.current.is_some() && column == Some(self.x - 1)
+    }
+
+    fn paint(&mut self, position: (usize, usize), color: PaletteColor) {
+        self.cells.push((position, color));
+    }
+
+    fn gap(&mut self) {
+        self.paint((self.x, 0), PaletteColor::White);
+        self.x += 1;
+    }
+
+    fn block(&mut self, color: PaletteColor) {
+        self.paint((self.x, 0), color);
+        self.current = Some((color, 1));
+        self.x += 1;
+    }
+
+    // the block the next command leaves, code after a halt starts with a new one
+    fn current(&mut self) -> (PaletteColor, usize) {
+        match self.current {
+            Some(current) => current,
+            None => {
+                self.block(FREE_COLOR);
+                (FREE_COLOR, 1)
+            }
+        }
+    }
+
+    fn command(&mut self, command: Command) {
+        let (color, _) = self.current();
+        self.block(next_color(color, command));
+    }
+
+    // pushes the size of a new block, unless the current block already has the right size
+    fn push(&mut self, value: u64) {
+        let (_, size) = self.current();
+        if size as u64 != value {
+            self.gap();
+            self.push_block(value as usize);
+        }
+        self.command(Command::Push);
+    }
+
+    // a block which is left to the right from its single codel in the last column
+    fn push_block(&mut self, size: usize) {
+        let rest = size - 1;
+        let columns = rest.div_ceil(PUSH_ROWS);
+        for column in 0..columns {
+            let rows = if column == 0 {
+                rest - (columns - 1) * PUSH_ROWS
+            } else {
+                PUSH_ROWS
+            };
+            for row in 0..rows {
+                self.paint((self.x + column, row), FREE_COLOR);
+            }
+        }
+        self.x += columns;
+        self.block(FREE_COLOR);
+        self.current = Some((FREE_COLOR, size));
+    }
+
+    fn push_value(&mut self, value: i64) {
+        if value > 0 {
+            self.push_positive(value as u64);
+        } else {
+            self.push(1);
+            self.command(Command::Not);
+            if value < 0 {
+                self.push_positive(value.unsigned_abs());
+                self.command(Command::Subtract);
+            }
+        }
+    }
+
+    fn push_positive(&mut self, value: u64) {
+        if value <= MAX_PUSH_BLOCK_SIZE {
+            self.push(value);
+            return;
+        }
+        let mut root = (value as f64).sqrt() as u64;
+        while root * root > value {
+            root -= 1;
+        }
+        while (root + 1) * (root + 1) <= value {
+            root += 1;
+        }
+        let remainder = value - root * (value / root);
+        self.push_positive(root);
+        self.push_positive(value / root);
+        self.command(Command::Multiply);
+        if remainder > 0 {
+            self.push_positive(remainder);
+            self.command(Command::Add);
+        }
+    }
+
+    // returns false if the label already exists
+    fn label(&mut self, name: &str) -> bool {
+        if self.labels.contains_key(name) {
+            return false;
+        }
+        if !self.is_previous_column(self.last_junction) {
+            // routes need white between their corners, which are next to branch and junction columns
+            if self.is_previous_column(self.last_branch) {
+                self.gap();
+            }
+            self.gap();
+            self.block(FREE_COLOR);
+            self.last_junction = Some(self.x - 1);
+        }
+        self.labels.insert(String::from(name), self.x - 1);
+        true
+    }
+
+    fn jump(&mut self, jump: Jump, label: &str, line: usize) {
+        if jump != Jump::IfNotZero && self.is_previous_column(self.last_junction) {
+            // same as for labels after branches, keep the branch away from the junction
+            self.gap();
+            self.block(FREE_COLOR);
+        }
+        match jump {
+            Jump::Always => self.push(1),
+            Jump::IfZero => self.command(Command::Not),
+            Jump::IfNotZero => {
+                self.command(Command::Not);
+                self.command(Command::Not);
+            }
+        }
+        self.command(Command::Pointer);
+        self.jumps.push((self.x - 1, String::from(label), line));
+        self.last_branch = Some(self.x - 1);
+    }
+
+    // a block which cannot be left in any direction: it is entered through white,
+    // and its leftmost codel hangs below the white
+    fn halt(&mut self) {
+        if self.current.is_none() {
+            return;
+        }
+        self.gap();
+        self.gap();
+        self.paint((self.x - 1, 1), FREE_COLOR);
+        self.paint((self.x, 1), FREE_COLOR);
+        self.block(FREE_COLOR);
+        self.current = None;
+        // keep the codel right of the halt black
+        self.x += 1;
+    }
+
+    fn route_jumps(&mut self) -> Result<(), RpietError> {
+        let height = PUSH_ROWS + JUMP_BAND_ROWS * self.jumps.len();
+        let mut routes = Vec::new();
+        for (index, (branch, label, line)) in self.jumps.iter().enumerate() {
+            let junction = *self
+                .labels
+                .get(label)
+                .ok_or_else(|| RpietError::InvalidAssembly {
+                    line: *line,
+                    message: format!("unknown label `{}`", label),
+                })?;
+            let track = PUSH_ROWS + JUMP_BAND_ROWS * (index + 1) - 1;
+            routes.push((*branch, junction, track));
+        }
+
+        // white first, the blocks of the routes are painted on top of it
+        for &(branch, junction, track) in routes.iter() {
+            for y in 1..height {
+                self.paint((branch, y), PaletteColor::White);
+            }
+            for x in branch.min(junction)..=branch.max(junction) {
+                self.paint((x, track), PaletteColor::White);
+            }
+        }
+        let junctions = routes
+            .iter()
+            .map(|&(_, junction, _)| junction)
+            .collect::<HashSet<_>>();
+        for &junction in junctions.iter() {
+            for y in 3..height {
+                self.paint((junction, y), PaletteColor::White);
+            }
+            // push 1 and turn from up to right into the junction
+            let turn = previous_color(FREE_COLOR, Command::Pointer);
+            self.paint((junction, 1), turn);
+            self.paint((junction, 2), previous_color(turn, Command::Push));
+        }
+
+        for &(branch, junction, track) in routes.iter() {
+            // both corners turn clockwise: once from down to left and from left to up, or
+            // three times from down to right and from right to up
+            let (turns, before_junction) = if junction < branch {
+                (1, junction + 1)
+            } else {
+                (3, junction - 1)
+            };
+            let mut y = track + 1 - (2 * turns + 2);
+            let mut color = FREE_COLOR;
+            // the first block pushes the turns for the second corner, the second for the first
+            for _ in 0..2 {
+                for _ in 0..turns {
+                    self.paint((branch, y), color);
+                    y += 1;
+                }
+                color = next_color(color, Command::Push);
+            }
+            self.paint((branch, y), color);
+            self.paint((branch, track), next_color(color, Command::Pointer));
+            self.paint((before_junction, track), FREE_COLOR);
+            self.paint((junction, track), next_color(FREE_COLOR, Command::Pointer));
+        }
+        Ok(())
+    }
+
+    fn into_rgb_rows(mut self) -> Result<RgbRows, RpietError> {
+        self.route_jumps()?;
+        let width = self
+            .cells
+            .iter()
+            .map(|&((x, _), _)| x + 1)
+            .max()
+            .unwrap_or(1);
+        let height = PUSH_ROWS + JUMP_BAND_ROWS * self.jumps.len();
+        let mut codels = vec![vec![PaletteColor::Black; width]; height];
+        for &((x, y), color) in self.cells.iter() {
+            codels[y][x] = color;
+        }
+        let palette = Palette::standard();
+        Ok(codels
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|color| palette.rgb(color).unwrap())
+                    .collect()
+            })
+            .collect())
+    }
+}
@@ -12,34 +12,16 @@ pub struct Block {
 }
 
 impl Block {
+    /// Returns the codel the interpreter leaves the block from with the given DP and CC,
+    /// `None` while the exits were not computed yet.
     pub fn exit_coordinates(
         &self,
         dp: &DirectionPointer,
         cc: &CodelChooser,
     ) -> Option<(usize, usize)> {
-        if let Some(block_exit) = &self.block_exit {
-            let coord = match dp {
-                DirectionPointer::Up => match cc {
-                    CodelChooser::Right => block_exit.exits[0][1],
-                    CodelChooser::Left => block_exit.exits[0][0],
-                },
-                DirectionPointer::Right => match cc {
-                    CodelChooser::Right => block_exit.exits[1][1],
-                    CodelChooser::Left => block_exit.exits[1][0],
-                },
-                DirectionPointer::Down => match cc {
-                    CodelChooser::Right => block_exit.exits[2][0],
-                    CodelChooser::Left => block_exit.exits[2][1],
-                },
-                DirectionPointer::Left => match cc {
-                    CodelChooser::Right => block_exit.exits[3][0],
-                    CodelChooser::Left => block_exit.exits[3][1],
-                },
-            };
-            Some(coord)
-        } else {
-            None
-        }
+        self.block_exit
+            .as_ref()
+            .map(|block_exit| block_exit.exit(*dp, *cc))
     }
 
     pub fn size(&self) -> usize {
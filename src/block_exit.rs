@@ -1,107 +1,60 @@
+use crate::counters::{CodelChooser, DirectionPointer};
+use crate::transition::{pointer_index, POINTER_STATES};
+
 /*
- * A BlockExit saves potential exit positions for a block of codels.
- * Depending on the DP and CC pointers, blocks have different exit points, which
- * we cache in this structure instead of calculating them on the fly.
+ * A BlockExit saves the codel the interpreter leaves a block from for each of
+ * the eight DP/CC combinations, instead of calculating them on every step.
+ *
+ * The exit is found like the Piet specification describes it: take the edge of
+ * the block which lies furthest in DP direction, then the codel of that edge which
+ * lies furthest in the direction CC points to. CC is relative to DP, so with DP
+ * down, CC left points to the right of the image and CC right to its left.
  */
 #[derive(Debug)]
 pub struct BlockExit {
-    pub exits: [[(usize, usize); 2]; 4],
+    exits: [(usize, usize); 8],
 }
 
 impl BlockExit {
-    pub fn from_coords(coords: &Vec<(usize, usize)>) -> BlockExit {
-        let top_coords = topmost_coords(coords);
-        let right_coords = rightmost_coords(coords);
-        let bottom_coords = bottommost_coords(coords);
-        let left_coords = leftmost_coords(coords);
-
-        BlockExit {
-            exits: [
-                [top_coords[0], top_coords[1]],
-                [right_coords[0], right_coords[1]],
-                [bottom_coords[0], bottom_coords[1]],
-                [left_coords[0], left_coords[1]],
-            ],
-        }
-    }
-}
-
-fn topmost_coords(coords: &Vec<(usize, usize)>) -> [(usize, usize); 2] {
-    let first = coords.first().unwrap();
-    let iter = coords.iter();
-    iter.fold([*first, *first], |[a, b], &coord| {
-        if coord.1 < a.1 {
-            [coord, coord]
-        } else if coord.1 == a.1 {
-            if coord.0 < a.0 {
-                [coord, b]
-            } else if coord.0 > b.0 {
-                [a, coord]
-            } else {
-                [a, b]
+    pub fn from_coords(coords: &[(usize, usize)]) -> BlockExit {
+        let mut exits = [coords[0]; 8];
+        for &coord in coords.iter() {
+            for (exit, &(dp, cc)) in exits.iter_mut().zip(POINTER_STATES.iter()) {
+                let side = cc_direction(dp, cc);
+                if (reach(coord, dp), reach(coord, side)) > (reach(*exit, dp), reach(*exit, side)) {
+                    *exit = coord;
+                }
             }
-        } else {
-            [a, b]
         }
-    })
-}
+        BlockExit { exits }
+    }
 
-fn bottommost_coords(coords: &Vec<(usize, usize)>) -> [(usize, usize); 2] {
-    let first = coords.first().unwrap();
-    let iter = coords.iter();
-    iter.fold([*first, *first], |[a, b], &coord| {
-        if coord.1 > a.1 {
-            [coord, coord]
-        } else if coord.1 == a.1 {
-            if coord.0 < a.0 {
-                [coord, b]
-            } else if coord.0 > b.0 {
-                [a, coord]
-            } else {
-                [a, b]
-            }
-        } else {
-            [a, b]
-        }
-    })
+    /// Returns the codel the interpreter leaves the block from with the given DP and CC.
+    pub fn exit(&self, dp: DirectionPointer, cc: CodelChooser) -> (usize, usize) {
+        self.exits[pointer_index(dp, cc)]
+    }
 }
 
-fn rightmost_coords(coords: &Vec<(usize, usize)>) -> [(usize, usize); 2] {
-    let first = coords.first().unwrap();
-    let iter = coords.iter();
-    iter.fold([*first, *first], |[a, b], &coord| {
-        if coord.0 > a.0 {
-            [coord, coord]
-        } else if coord.0 == a.0 {
-            if coord.1 < a.1 {
-                [coord, b]
-            } else if coord.1 > b.1 {
-                [a, coord]
-            } else {
-                [a, b]
-            }
-        } else {
-            [a, b]
-        }
-    })
+// the absolute direction CC points to: left turns DP counterclockwise, right clockwise
+fn cc_direction(dp: DirectionPointer, cc: CodelChooser) -> DirectionPointer {
+    match (dp, cc) {
+        (DirectionPointer::Up, CodelChooser::Left) => DirectionPointer::Left,
+        (DirectionPointer::Up, CodelChooser::Right) => DirectionPointer::Right,
+        (DirectionPointer::Right, CodelChooser::Left) => DirectionPointer::Up,
+        (DirectionPointer::Right, CodelChooser::Right) => DirectionPointer::Down,
+        (DirectionPointer::Down, CodelChooser::Left) => DirectionPointer::Right,
+        (DirectionPointer::Down, CodelChooser::Right) => DirectionPointer::Left,
+        (DirectionPointer::Left, CodelChooser::Left) => DirectionPointer::Down,
+        (DirectionPointer::Left, CodelChooser::Right) => DirectionPointer::Up,
+    }
 }
 
-fn leftmost_coords(coords: &Vec<(usize, usize)>) -> [(usize, usize); 2] {
-    let first = coords.first().unwrap();
-    let iter = coords.iter();
-    iter.fold([*first, *first], |[a, b], &coord| {
-        if coord.0 < a.0 {
-            [coord, coord]
-        } else if coord.0 == a.0 {
-            if coord.1 < a.1 {
-                [coord, b]
-            } else if coord.1 > b.1 {
-                [a, coord]
-            } else {
-                [a, b]
-            }
-        } else {
-            [a, b]
-        }
-    })
+// how far a codel lies in the given direction, larger values are further
+fn reach((x, y): (usize, usize), direction: DirectionPointer) -> isize {
+    match direction {
+        DirectionPointer::Up => -(y as isize),
+        DirectionPointer::Right => x as isize,
+        DirectionPointer::Down => y as isize,
+        DirectionPointer::Left => -(x as isize),
+    }
 }
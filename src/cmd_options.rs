@@ -1,12 +1,42 @@
-use clap::{App, Arg, ArgMatches};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use rpiet::{ArithmeticPolicy, CodelSize, Compat, CompileTarget, Config, Palette, UnknownColor};
+
+pub enum Mode {
+    Run,
+    Debug,
+    Compile(CompileTarget),
+    Assemble,
+    Disasm,
+    Lint(LintFormat),
+}
+
+pub enum LintFormat {
+    Text,
+    Json,
+}
+
+pub enum NumberBackend {
+    Checked,
+    Wrapping,
+    #[cfg(feature = "bignum")]
+    BigInt,
+}
+
+#[cfg(feature = "bignum")]
+const NUMBER_BACKENDS: &[&str] = &["checked", "wrapping", "bigint"];
+#[cfg(not(feature = "bignum"))]
+const NUMBER_BACKENDS: &[&str] = &["checked", "wrapping"];
 
 pub struct CmdOptions<'a> {
-    pub verbose: bool,
-    pub codel_size: u32,
-    pub max_steps: u128,
-    pub unlimited_steps: bool,
-    pub unknown_white: bool,
+    pub mode: Mode,
     pub file_path: &'a str,
+    pub trace_path: Option<&'a str>,
+    pub render_trace_path: Option<&'a str>,
+    pub output_path: Option<&'a str>,
+    pub number_backend: NumberBackend,
+    pub palette_path: Option<&'a str>,
+    pub color_tolerance: Option<u8>,
+    pub config: Config,
 }
 
 fn is_valid_file_name(val: String) -> Result<(), String> {
@@ -24,77 +54,433 @@ pub fn clap_options() -> ArgMatches<'static> {
         .version(clap::crate_version!())
         .author("Philipp Tessenow <philipp@tessenow.org>")
         .about("An interpreter for the piet programming language")
-        .arg(
-            Arg::with_name("file")
-                .help("The image to execute. Supports png and gif files only")
-                .default_value("input.png")
-                .index(1)
-                .required(true)
-                .validator(is_valid_file_name),
+        .args(&image_args())
+        .subcommand(
+            SubCommand::with_name("debug")
+                .about("Executes an image step by step in an interactive debugger")
+                .args(&image_args()),
         )
-        .arg(
-            Arg::with_name("codel_size")
-                .help("The length of a codel in pixels")
-                .default_value("1")
-                .short("c")
-                .long("codel-size")
-                .long_help(
-                    "Piet works by going through the pixels of an image.\n\
-                    However, this makes piet images visually small when viewing them.\n\
-                    Thus, piet allows interpreting images in codels which consist of larger pixels blocks.\n\
-                    Setting codel-size to 2 would mean a codel is the size of 2x2 pixels.",
+        .subcommand(
+            SubCommand::with_name("compile")
+                .about("Translates an image into a standalone C or Rust program")
+                .args(&image_args())
+                .arg(
+                    Arg::with_name("emit")
+                        .help("The language of the generated program")
+                        .long("emit")
+                        .long_help(
+                            "Selects the language of the generated program. The program behaves\n\
+                            like rpiet with its default options: 64 bit numbers which skip\n\
+                            overflowing commands and no step limit. It follows `--compat`,\n\
+                            options which would change numbers, input or the step limit\n\
+                            are rejected.\n\
+                            c:    a single C file, build it with `cc -O2 -o program program.c`\n\
+                            rust: a single Rust file, build it with `rustc -O program.rs`",
+                        )
+                        .takes_value(true)
+                        .value_name("language")
+                        .possible_values(&["c", "rust"])
+                        .default_value("c")
+                        .required(false),
                 )
-                .takes_value(true)
-                .required(false)
-                .validator(|s| {
-                    s.parse::<u32>()
-                        .map(|_| ())
-                        .map_err(|_| String::from("Must be a positive number!"))
-                }),
+                .arg(
+                    Arg::with_name("output")
+                        .help("Writes the generated program to the given file instead of stdout")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .value_name("file")
+                        .required(false),
+                ),
         )
-        .arg(
-            Arg::with_name("max_steps")
-                .help("The max number of allowed execution steps")
-                .short("e")
-                .long("max-steps")
-                .long_help(
-                    "This stops the piet interpreter after the given number of steps and\n\
-                    solves the halting problem once and for all :)\n\
-                    Very useful to debug endless loops",
-                )
-                .takes_value(true)
-                .required(false)
-                .validator(|s| {
-                    s.parse::<u32>()
-                        .map(|_| ())
-                        .map_err(|_| String::from("Must be a positive number!"))
-                }),
+        .subcommand(
+            SubCommand::with_name("disasm")
+                .about("Lists the steps an image can take as a readable control-flow listing")
+                .args(&image_args()),
         )
-        .arg(
-            Arg::with_name("verbose")
-                .help("Logs debug information to stderr")
-                .short("v")
-                .long("verbose")
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Checks an image for unreachable blocks, endless loops and broken colors")
+                .args(&image_args())
+                .arg(
+                    Arg::with_name("format")
+                        .help("The format of the reported problems")
+                        .long("format")
+                        .long_help(
+                            "Selects how the problems are printed, one per line:\n\
+                            text: `<file>:<x>:<y>: <kind>: <message>`, x and y count codels\n\
+                            json: one JSON object with kind, x, y and message per line",
+                        )
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .required(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("assemble")
+                .about("Lays out a program written in the rpiet assembly language as a png image")
+                .arg(
+                    Arg::with_name("file")
+                        .help("The assembly source")
+                        .long_help(
+                            "The assembly source, one instruction per line:\n\
+                            push <N>, pop, add, sub, mul, div, mod, not, gt, dup, roll,\n\
+                            inn, inc, outn, outc, jmp <label>, jz <label>, jnz <label> and halt.\n\
+                            Labels end with a colon, # and ; start comments.",
+                        )
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("The png file to write")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .value_name("out.png")
+                        .required(true)
+                        .validator(|s| {
+                            if s.ends_with(".png") {
+                                Ok(())
+                            } else {
+                                Err(String::from("the output file must be a png file."))
+                            }
+                        }),
+                )
+                .arg(
+                    Arg::with_name("codel_size")
+                        .help("The length of a codel in pixels")
+                        .short("c")
+                        .long("codel-size")
+                        .takes_value(true)
+                        .default_value("1")
+                        .validator(|s| match s.parse::<u32>() {
+                            Ok(n) if n > 0 => Ok(()),
+                            _ => Err(String::from("Must be a positive number!")),
+                        }),
+                ),
         )
         .get_matches()
 }
 
+fn image_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("file")
+            .help("The image to execute. Supports png and gif files only")
+            .default_value("input.png")
+            .index(1)
+            .required(true)
+            .validator(is_valid_file_name),
+        Arg::with_name("codel_size")
+            .help("The length of a codel in pixels, or \"auto\" to detect it from the image")
+            .default_value("auto")
+            .short("c")
+            .long("codel-size")
+            .long_help(
+                "Piet works by going through the pixels of an image.\n\
+                However, this makes piet images visually small when viewing them.\n\
+                Thus, piet allows interpreting images in codels which consist of larger pixels blocks.\n\
+                Setting codel-size to 2 would mean a codel is the size of 2x2 pixels.\n\
+                The default \"auto\" picks the largest codel size consistent with the image\n\
+                and falls back to 1 when the image is ambiguous.",
+            )
+            .takes_value(true)
+            .required(false)
+            .validator(|s| {
+                if s == "auto" {
+                    return Ok(());
+                }
+                match s.parse::<u32>() {
+                    Ok(n) if n > 0 => Ok(()),
+                    _ => Err(String::from("Must be a positive number or \"auto\"!")),
+                }
+            }),
+        Arg::with_name("max_steps")
+            .help("The max number of allowed execution steps")
+            .short("e")
+            .long("max-steps")
+            .long_help(
+                "This stops the piet interpreter after the given number of steps and\n\
+                solves the halting problem once and for all :)\n\
+                Very useful to debug endless loops",
+            )
+            .takes_value(true)
+            .required(false)
+            .validator(|s| {
+                s.parse::<u32>()
+                    .map(|_| ())
+                    .map_err(|_| String::from("Must be a positive number!"))
+            }),
+        Arg::with_name("trace")
+            .help("Writes a machine-readable trace of every execution step to the given file")
+            .long("trace")
+            .long_help(
+                "Writes one JSON object per execution step (JSON Lines) to the given file.\n\
+                Every line holds the step counter, old and new position, DP, CC, the event\n\
+                (an executed command, a white slide, a pointer toggle or the exit),\n\
+                the size of the block that was left and a snapshot of the stack.",
+            )
+            .takes_value(true)
+            .value_name("file")
+            .required(false),
+        Arg::with_name("render_trace")
+            .help("Draws the path the interpreter took on top of the image and saves it as png")
+            .long("render-trace")
+            .long_help(
+                "Renders a scaled up copy of the image with the path the interpreter took:\n\
+                solid black arrows for executed commands, dashed gray arrows for slides\n\
+                through white codels and orange crosses where DP or CC got toggled.\n\
+                Color blocks are darker the less often they were entered.",
+            )
+            .takes_value(true)
+            .value_name("out.png")
+            .required(false)
+            .validator(|s| {
+                if s.ends_with(".png") {
+                    Ok(())
+                } else {
+                    Err(String::from("the output file must be a png file."))
+                }
+            }),
+        Arg::with_name("numbers")
+            .help("The kind of numbers on the stack")
+            .long("numbers")
+            .long_help(
+                "Selects how numbers on the stack are stored:\n\
+                checked:  64 bit integers, commands which would overflow are skipped\n\
+                wrapping: 64 bit integers which wrap around on overflow\n\
+                bigint:   integers of arbitrary size (needs the `bignum` cargo feature)",
+            )
+            .takes_value(true)
+            .possible_values(NUMBER_BACKENDS)
+            .default_value("checked")
+            .required(false),
+        Arg::with_name("unknown_color")
+            .help("How to interpret pixels whose color is not one of the 20 Piet colors")
+            .long("unknown-color")
+            .long_help(
+                "Selects how pixels with a color outside of the Piet palette are interpreted:\n\
+                white:   as white codels\n\
+                black:   as black codels\n\
+                nearest: as the perceptually nearest of the 20 Piet colors\n\
+                error:   not at all, rpiet stops with an error\n\
+                All unknown colors are listed in a single warning on stderr.",
+            )
+            .takes_value(true)
+            .value_name("mode")
+            .possible_values(&["white", "black", "nearest", "error"])
+            .default_value("white")
+            .required(false),
+        Arg::with_name("palette")
+            .help("Reads the colors of the image from a palette file instead of the standard Piet colors")
+            .long("palette")
+            .long_help(
+                "Reads the mapping from RGB values to Piet colors from a text file with one\n\
+                `<color name> <RGB value>` entry per line, e.g. `dark red #C00000`.\n\
+                Color names are black, white and red, yellow, green, cyan, blue or magenta\n\
+                with an optional `light` or `dark` prefix. A color may have several entries.\n\
+                A `tolerance <N>` line sets the color tolerance, lines starting with # are comments.",
+            )
+            .takes_value(true)
+            .value_name("file")
+            .required(false),
+        Arg::with_name("color_tolerance")
+            .help("How much each RGB channel of a pixel may differ from a palette color")
+            .long("color-tolerance")
+            .long_help(
+                "Pixels match a palette color if none of their red, green and blue channels\n\
+                differ by more than this amount, the closest palette color wins.\n\
+                Defaults to 0 (exact matches only) or the tolerance of the palette file.",
+            )
+            .takes_value(true)
+            .value_name("0-255")
+            .required(false)
+            .validator(|s| {
+                s.parse::<u8>()
+                    .map(|_| ())
+                    .map_err(|_| String::from("Must be a number from 0 to 255!"))
+            }),
+        Arg::with_name("overflow")
+            .help("What to do when an arithmetic command overflows")
+            .long("overflow")
+            .long_help(
+                "Selects what happens when ADD, SUBTRACT, MULTIPLY, DIVIDE or MOD overflow:\n\
+                ignore:   skip the command and leave the stack untouched\n\
+                wrap:     wrap around at the bounds of 64 bit integers\n\
+                saturate: clamp the result to the bounds of 64 bit integers\n\
+                halt:     stop the program with an error\n\
+                Has no effect with `--numbers=wrapping` and `--numbers=bigint`,\n\
+                which never overflow.",
+            )
+            .takes_value(true)
+            .value_name("policy")
+            .possible_values(&["ignore", "wrap", "saturate", "halt"])
+            .default_value("ignore")
+            .required(false),
+        Arg::with_name("compat")
+            .help("Which interpreter to behave like in corner cases where Piet interpreters disagree")
+            .long("compat")
+            .long_help(
+                "Selects how corner cases are handled which Piet interpreters disagree on:\n\
+                spec:  slides through white turn at black codels and edges until they reach\n\
+                       a color block or get trapped, which ends the program. ROLL with a\n\
+                       negative depth and DIVIDE or MOD by zero are ignored and keep their\n\
+                       values on the stack\n\
+                npiet: like spec, but ROLL with a negative depth pops its values\n\
+                rpiet: like rpiet <= 0.3, slides through white stop at the first black\n\
+                       codel or edge and toggle CC or DP there, DIVIDE and MOD by zero\n\
+                       pop their values, a blocked move after a move goes on toggling\n\
+                       where the last one stopped, MOD is never negative and SWITCH\n\
+                       keeps CC for negative odd values",
+            )
+            .takes_value(true)
+            .value_name("preset")
+            .possible_values(&["spec", "npiet", "rpiet"])
+            .default_value("spec")
+            .required(false),
+        Arg::with_name("line_based_in_number")
+            .help("Makes IN_NUMBER read a whole line of input and discard the rest of it")
+            .long("line-based-in-number")
+            .long_help(
+                "By default IN_NUMBER only consumes leading whitespace, an optional sign and\n\
+                digits, so a following IN_CHAR reads the character right after the number.\n\
+                With this flag IN_NUMBER reads a whole line and discards everything after\n\
+                the number, like older versions of rpiet did.",
+            ),
+        Arg::with_name("raw_byte_in_char")
+            .help("Makes IN_CHAR read single bytes instead of UTF-8 encoded characters")
+            .long("raw-byte-in-char")
+            .long_help(
+                "By default IN_CHAR decodes one UTF-8 encoded character from the input and\n\
+                pushes its Unicode code point, the same values OUT_CHAR prints.\n\
+                With this flag IN_CHAR pushes the value of a single byte instead,\n\
+                like older versions of rpiet did.",
+            ),
+        Arg::with_name("precompute_transitions")
+            .help("Computes all moves between color blocks before running the image")
+            .long("precompute-transitions")
+            .long_help(
+                "Computes where each color block leads for all eight DP and CC combinations\n\
+                before the first step, so every later step is a table lookup.\n\
+                Speeds up long running programs at the cost of a slower start.",
+            ),
+        Arg::with_name("verbose")
+            .help("Logs debug information to stderr")
+            .short("v")
+            .long("verbose"),
+    ]
+}
+
+// the generated programs always use 64 bit numbers which skip overflowing commands, read input
+// like rpiet does by default and take no step limit, see `--emit`
+const OPTIONS_COMPILE_IGNORES: &[&str] = &[
+    "overflow",
+    "numbers",
+    "line_based_in_number",
+    "raw_byte_in_char",
+    "max_steps",
+];
+
+fn reject_options_compile_ignores(options: &ArgMatches) {
+    for name in OPTIONS_COMPILE_IGNORES {
+        // `occurrences_of` does not count default values
+        if options.occurrences_of(name) > 0 {
+            clap::Error::with_description(
+                &format!(
+                    "--{} is not supported by compile, the generated program behaves like rpiet with its default options",
+                    name.replace('_', "-")
+                ),
+                clap::ErrorKind::ArgumentConflict,
+            )
+            .exit();
+        }
+    }
+}
+
 pub fn cmd_options<'a>(options: &'a ArgMatches) -> CmdOptions<'a> {
+    let (mode, options) = match options.subcommand() {
+        ("debug", Some(debug_options)) => (Mode::Debug, debug_options),
+        ("compile", Some(compile_options)) => {
+            reject_options_compile_ignores(compile_options);
+            let target = match compile_options.value_of("emit") {
+                Some("rust") => CompileTarget::Rust,
+                _ => CompileTarget::C,
+            };
+            (Mode::Compile(target), compile_options)
+        }
+        ("assemble", Some(assemble_options)) => (Mode::Assemble, assemble_options),
+        ("disasm", Some(disasm_options)) => (Mode::Disasm, disasm_options),
+        ("lint", Some(lint_options)) => {
+            let format = match lint_options.value_of("format") {
+                Some("json") => LintFormat::Json,
+                _ => LintFormat::Text,
+            };
+            (Mode::Lint(format), lint_options)
+        }
+        _ => (Mode::Run, options),
+    };
     let verbose = options.is_present("verbose");
     let codel_size = options
         .value_of("codel_size")
-        .map_or(1, |s| s.parse::<u32>().unwrap_or(1));
+        .and_then(|s| s.parse::<u32>().ok())
+        .map_or(CodelSize::Auto, CodelSize::Fixed);
     let max_steps = options
         .value_of("max_steps")
         .map_or(-1, |s| s.parse::<i128>().unwrap_or(-1));
     let file_path = options.value_of("file").unwrap();
+    let trace_path = options.value_of("trace");
+    let render_trace_path = options.value_of("render_trace");
+    let output_path = options.value_of("output");
+    let number_backend = match options.value_of("numbers") {
+        Some("wrapping") => NumberBackend::Wrapping,
+        #[cfg(feature = "bignum")]
+        Some("bigint") => NumberBackend::BigInt,
+        _ => NumberBackend::Checked,
+    };
+    let palette_path = options.value_of("palette");
+    let color_tolerance = options
+        .value_of("color_tolerance")
+        .and_then(|s| s.parse::<u8>().ok());
+    let unknown_color = match options.value_of("unknown_color") {
+        Some("black") => UnknownColor::Black,
+        Some("nearest") => UnknownColor::Nearest,
+        Some("error") => UnknownColor::Error,
+        _ => UnknownColor::White,
+    };
+    let compat = match options.value_of("compat") {
+        Some("npiet") => Compat::Npiet,
+        Some("rpiet") => Compat::Rpiet,
+        _ => Compat::Spec,
+    };
+    let arithmetic_policy = match options.value_of("overflow") {
+        Some("wrap") => ArithmeticPolicy::Wrap,
+        Some("saturate") => ArithmeticPolicy::Saturate,
+        Some("halt") => ArithmeticPolicy::Halt,
+        _ => ArithmeticPolicy::Ignore,
+    };
 
     CmdOptions {
-        verbose: verbose,
-        codel_size: codel_size,
-        max_steps: if max_steps < 0 { 0 } else { max_steps as u128 },
-        unlimited_steps: max_steps < 0,
-        file_path: file_path,
-        unknown_white: true, // TODO: add a command line option so the user can configure this
+        mode,
+        file_path,
+        trace_path,
+        render_trace_path,
+        output_path,
+        number_backend,
+        palette_path,
+        color_tolerance,
+        config: Config {
+            verbose,
+            codel_size,
+            max_steps: if max_steps < 0 { 0 } else { max_steps as u128 },
+            unlimited_steps: max_steps < 0,
+            unknown_color,
+            palette: Palette::standard(), // replaced by the palette file in main, if given
+            arithmetic_policy,
+            line_based_in_number: options.is_present("line_based_in_number"),
+            raw_byte_in_char: options.is_present("raw_byte_in_char"),
+            precompute_transitions: options.is_present("precompute_transitions"),
+            compat,
+        },
     }
 }
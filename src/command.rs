@@ -1,9 +1,14 @@
 use std::char;
-use std::io::Read;
+use std::fmt;
+use std::io::{BufRead, Write};
 
+use crate::config::{ArithmeticPolicy, Config};
 use crate::counters::{CodelChooser, DirectionPointer};
+use crate::error::RpietError;
+use crate::number::Number;
+use crate::streams::Streams;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Command {
     Push,
     Pop,
@@ -48,20 +53,45 @@ impl Command {
         }
     }
 
-    pub fn execute(
+    /// The light and hue difference between two blocks which executes this command, the reverse of `Command::from`.
+    pub fn diffs(&self) -> (u8, u8) {
+        match self {
+            Command::Add => (0, 1),
+            Command::Divide => (0, 2),
+            Command::Greater => (0, 3),
+            Command::Duplicate => (0, 4),
+            Command::InChar => (0, 5),
+            Command::Push => (1, 0),
+            Command::Subtract => (1, 1),
+            Command::Mod => (1, 2),
+            Command::Pointer => (1, 3),
+            Command::Roll => (1, 4),
+            Command::OutNumber => (1, 5),
+            Command::Pop => (2, 0),
+            Command::Multiply => (2, 1),
+            Command::Not => (2, 2),
+            Command::Switch => (2, 3),
+            Command::InNumber => (2, 4),
+            Command::OutChar => (2, 5),
+        }
+    }
+
+    pub(crate) fn execute<N: Number>(
         &self,
-        stack: &mut Vec<i64>,
+        stack: &mut Vec<N>,
         dp: &mut DirectionPointer,
         cc: &mut CodelChooser,
         block_size: usize,
-        verbose_logging: bool,
-    ) {
+        streams: &mut Streams,
+        config: &Config,
+    ) -> Result<(), RpietError> {
+        let verbose_logging = config.verbose;
         match self {
             Command::Push => {
                 if verbose_logging {
                     eprintln!("execute PUSH({})", block_size);
                 }
-                stack.push(block_size as i64)
+                stack.push(N::from_i64(block_size as i64))
             }
             Command::Pop => {
                 if stack.pop().is_some() {
@@ -75,110 +105,31 @@ impl Command {
                 }
             }
             Command::Duplicate => {
-                if let Some(&last) = stack.last() {
+                if let Some(last) = stack.last() {
                     if verbose_logging {
                         eprintln!("execute DUPLICATE");
                     }
-                    stack.push(last)
+                    stack.push(last.clone())
                 } else {
                     if verbose_logging {
                         eprintln!("skip executing DUPLICATE due to empty stack");
                     }
                 }
             }
-            Command::Add => {
-                if stack.len() >= 2 {
-                    let a = stack.pop().unwrap();
-                    let b = stack.pop().unwrap();
-                    if verbose_logging {
-                        eprintln!("execute ADD({}, {})", b, a);
-                    }
-                    stack.push(a + b);
-                } else {
-                    if verbose_logging {
-                        eprintln!("skip executing ADD due to not enough values on the stack");
-                    }
-                }
-            }
-            Command::Subtract => {
-                if stack.len() >= 2 {
-                    let a = stack.pop().unwrap();
-                    let b = stack.pop().unwrap();
-                    if verbose_logging {
-                        eprintln!("execute SUBTRACT({}, {})", b, a);
-                    }
-                    stack.push(b - a);
-                } else {
-                    if verbose_logging {
-                        eprintln!("skip executing SUBTRACT due to not enough values on the stack");
-                    }
-                }
-            }
-            Command::Multiply => {
-                if stack.len() >= 2 {
-                    let a = stack.pop().unwrap();
-                    let b = stack.pop().unwrap();
-                    if verbose_logging {
-                        eprintln!("execute MULTIPLY({}, {})", b, a);
-                    }
-                    stack.push(a * b);
-                } else {
-                    if verbose_logging {
-                        eprintln!("skip executing MULTIPLY due to not enough values on the stack");
-                    }
-                }
-            }
-            Command::Divide => {
-                if stack.len() >= 2 {
-                    let a = stack.pop().unwrap();
-                    let b = stack.pop().unwrap();
-                    if a == 0 {
-                        if verbose_logging {
-                            eprintln!(
-                                "skip executing DIVIDE due to not being able to divide by zero"
-                            );
-                        }
-                    } else {
-                        if verbose_logging {
-                            eprintln!("execute DIVIDE({}, {})", b, a);
-                        }
-                        stack.push(b / a);
-                    }
-                } else {
-                    if verbose_logging {
-                        eprintln!("skip executing DIVIDE due to not enough values on the stack");
-                    }
-                }
-            }
-            Command::Mod => {
-                if stack.len() >= 2 {
-                    let a = stack.pop().unwrap();
-                    let b = stack.pop().unwrap();
-                    if a == 0 {
-                        if verbose_logging {
-                            eprintln!("skip executing MOD due to not being able to modulo by zero");
-                        }
-                    } else {
-                        if verbose_logging {
-                            eprintln!("execute MOD({}, {})", b, a);
-                        }
-                        stack.push(b.rem_euclid(a));
-                    }
-                } else {
-                    if verbose_logging {
-                        eprintln!("skip executing MOD due to not enough values on the stack");
-                    }
-                }
-            }
+            Command::Add
+            | Command::Subtract
+            | Command::Multiply
+            | Command::Divide
+            | Command::Mod => execute_arithmetic(*self, stack, config)?,
             Command::Not => {
                 if let Some(a) = stack.pop() {
                     if verbose_logging {
                         eprintln!("execute NOT({})", a);
                     }
-                    if a == 0 {
-                        stack.push(1);
+                    if a.is_zero() {
+                        stack.push(N::from_i64(1));
                     } else {
-                        stack.push(0);
+                        stack.push(N::from_i64(0));
                     }
                 } else {
                     if verbose_logging {
@@ -194,9 +145,9 @@ impl Command {
                         eprintln!("execute GREATER({}, {})", b, a);
                     }
                     if b > a {
-                        stack.push(1);
+                        stack.push(N::from_i64(1));
                     } else {
-                        stack.push(0);
+                        stack.push(N::from_i64(0));
                     }
                 } else {
                     if verbose_logging {
@@ -209,7 +160,7 @@ impl Command {
                     if verbose_logging {
                         eprintln!("execute POINTER({})", a);
                     }
-                    match a % 4 {
+                    match a.rem_i64(4) {
                         3 => turn_direction_pointer(dp, 3),
                         2 => turn_direction_pointer(dp, 2),
                         1 => turn_direction_pointer(dp, 1),
@@ -229,7 +180,13 @@ impl Command {
                     if verbose_logging {
                         eprintln!("execute SWITCH({})", a);
                     }
-                    if a % 2 == 1 {
+                    // -1 is odd as well, rpiet <= 0.3 only toggled for positive values, see `Compat`
+                    let odd = if config.compat.switches_for_negative_odd_values() {
+                        a.rem_i64(2) != 0
+                    } else {
+                        a.rem_i64(2) == 1
+                    };
+                    if odd {
                         *cc = match cc {
                             CodelChooser::Left => CodelChooser::Right,
                             CodelChooser::Right => CodelChooser::Left,
@@ -245,14 +202,18 @@ impl Command {
                 if stack.len() >= 2 {
                     let rolls = stack.pop().unwrap();
                     let depth = stack.pop().unwrap();
-                    if depth <= 0 {
+                    if depth <= N::from_i64(0) {
                         if verbose_logging {
                             eprintln!("skip executing ROLL must be a positive roll depth");
                         }
-                        stack.push(depth);
-                        stack.push(rolls);
+                        // npiet drops the values of a negative depth, see `Compat`
+                        if depth.is_zero() || config.compat.keeps_values_of_negative_roll() {
+                            stack.push(depth);
+                            stack.push(rolls);
+                        }
                     } else {
-                        if stack.len() < depth as usize {
+                        let depth_value = depth.to_i64().unwrap_or(i64::MAX);
+                        if (stack.len() as i64) < depth_value {
                             if verbose_logging {
                                 eprintln!(
                                     "skip executing ROLL due to not enough values on the stack"
@@ -264,13 +225,14 @@ impl Command {
                             if verbose_logging {
                                 eprintln!("execute ROLL({}, {})", depth, rolls);
                             }
-                            let rolls = rolls % depth;
-                            let mut substack: Vec<_> =
-                                stack.drain(stack.len() - (depth as usize)..).collect();
+                            let rolls = rolls.rem_i64(depth_value);
+                            let mut substack: Vec<_> = stack
+                                .drain(stack.len() - (depth_value as usize)..)
+                                .collect();
                             if rolls > 0 {
                                 substack.rotate_right(rolls as usize);
                             } else {
-                                substack.rotate_left((rolls * -1) as usize);
+                                substack.rotate_left(-rolls as usize);
                             }
                             stack.append(&mut substack);
                         }
@@ -286,7 +248,9 @@ impl Command {
                     if verbose_logging {
                         eprintln!("execute OUT_NUM({})", last);
                     }
-                    print!("{}", last);
+                    if write!(streams.output, "{}", last).is_err() && verbose_logging {
+                        eprintln!("could not write OUT_NUM({}) to the output", last);
+                    }
                 } else {
                     if verbose_logging {
                         eprintln!("skip executing OUT_NUM due to empty stack");
@@ -295,13 +259,16 @@ impl Command {
             }
             Command::OutChar => {
                 if let Some(last) = stack.pop() {
-                    if last >= 0 && last <= (u32::max_value() as i64) {
-                        let c = char::from_u32(last as u32);
+                    let code_point = last.to_i64().filter(|&c| c >= 0 && c <= u32::MAX as i64);
+                    if let Some(code_point) = code_point {
+                        let c = char::from_u32(code_point as u32);
                         if let Some(c) = c {
                             if verbose_logging {
                                 eprintln!("execute OUT_CHAR({} -> {})", last, c);
                             }
-                            print!("{}", c);
+                            if write!(streams.output, "{}", c).is_err() && verbose_logging {
+                                eprintln!("could not write OUT_CHAR({}) to the output", c);
+                            }
                         } else {
                             if verbose_logging {
                                 eprintln!("skip executing OUT_CHAR due invalid char");
@@ -321,9 +288,18 @@ impl Command {
                 }
             }
             Command::InNumber => {
-                let mut buffer = String::new();
-                if let Ok(_) = std::io::stdin().read_line(&mut buffer) {
-                    if let Ok(num) = buffer.trim().parse::<i64>() {
+                let _ = streams.output.flush();
+                let text = if config.line_based_in_number {
+                    let mut buffer = String::new();
+                    streams
+                        .input
+                        .read_line(&mut buffer)
+                        .map(|_| Some(buffer.trim().to_string()))
+                } else {
+                    streams.read_number_text()
+                };
+                if let Ok(text) = text {
+                    if let Some(num) = text.as_deref().and_then(N::parse) {
                         if verbose_logging {
                             eprintln!("executed IN_NUM({})", num);
                         }
@@ -340,21 +316,23 @@ impl Command {
                 }
             }
             Command::InChar => {
-                let input: Option<u8> = std::io::stdin()
-                    .bytes()
-                    .next()
-                    .and_then(|result| result.ok());
+                let _ = streams.output.flush();
+                let input: Option<u32> = if config.raw_byte_in_char {
+                    streams.read_byte().ok().flatten().map(u32::from)
+                } else {
+                    streams.read_char().ok().flatten().map(u32::from)
+                };
 
                 match input {
-                    Some(byte) => {
+                    Some(value) => {
                         if verbose_logging {
                             eprintln!(
                                 "executed IN_CHAR({} -> {})",
-                                char::from_u32(byte as u32).unwrap(),
-                                byte as i64
+                                char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER),
+                                value
                             );
                         }
-                        stack.push(byte as i64);
+                        stack.push(N::from_i64(value as i64));
                     }
                     None => {
                         if verbose_logging {
@@ -364,6 +342,147 @@ impl Command {
                 }
             }
         }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Command::Push => "PUSH",
+            Command::Pop => "POP",
+            Command::Add => "ADD",
+            Command::Subtract => "SUBTRACT",
+            Command::Multiply => "MULTIPLY",
+            Command::Divide => "DIVIDE",
+            Command::Mod => "MOD",
+            Command::Not => "NOT",
+            Command::Greater => "GREATER",
+            Command::Pointer => "POINTER",
+            Command::Switch => "SWITCH",
+            Command::Duplicate => "DUPLICATE",
+            Command::Roll => "ROLL",
+            Command::InNumber => "IN_NUMBER",
+            Command::InChar => "IN_CHAR",
+            Command::OutNumber => "OUT_NUMBER",
+            Command::OutChar => "OUT_CHAR",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// ADD, SUBTRACT, MULTIPLY, DIVIDE and MOD, overflows are handled according to the configured ArithmeticPolicy
+fn execute_arithmetic<N: Number>(
+    command: Command,
+    stack: &mut Vec<N>,
+    config: &Config,
+) -> Result<(), RpietError> {
+    let verbose_logging = config.verbose;
+    if stack.len() < 2 {
+        if verbose_logging {
+            eprintln!(
+                "skip executing {} due to not enough values on the stack",
+                command
+            );
+        }
+        return Ok(());
+    }
+    let a = stack.pop().unwrap();
+    let b = stack.pop().unwrap();
+    if a.is_zero() && (command == Command::Divide || command == Command::Mod) {
+        if verbose_logging {
+            let operation = if command == Command::Divide {
+                "divide"
+            } else {
+                "modulo"
+            };
+            eprintln!(
+                "skip executing {} due to not being able to {} by zero",
+                command, operation
+            );
+        }
+        // rpiet <= 0.3 dropped both values, see `Compat`
+        if config.compat.keeps_values_of_division_by_zero() {
+            stack.push(b);
+            stack.push(a);
+        }
+        return Ok(());
+    }
+
+    let checked_result = match command {
+        Command::Add => b.checked_add(&a),
+        Command::Subtract => b.checked_sub(&a),
+        Command::Multiply => b.checked_mul(&a),
+        Command::Divide => b.checked_div(&a),
+        _ => b
+            .checked_rem_euclid(&a)
+            .map(|remainder| with_sign_of_divisor(remainder, &a, config)),
+    };
+    if let Some(result) = checked_result {
+        if verbose_logging {
+            eprintln!("execute {}({}, {})", command, b, a);
+        }
+        stack.push(result);
+        return Ok(());
+    }
+
+    let policy = config.arithmetic_policy;
+    let result = match policy {
+        ArithmeticPolicy::Wrap => Some(match command {
+            Command::Add => b.wrapping_add(&a),
+            Command::Subtract => b.wrapping_sub(&a),
+            Command::Multiply => b.wrapping_mul(&a),
+            Command::Divide => b.wrapping_div(&a),
+            _ => with_sign_of_divisor(b.wrapping_rem_euclid(&a), &a, config),
+        }),
+        ArithmeticPolicy::Saturate => Some(match command {
+            Command::Add => b.saturating_add(&a),
+            Command::Subtract => b.saturating_sub(&a),
+            Command::Multiply => b.saturating_mul(&a),
+            Command::Divide => b.saturating_div(&a),
+            _ => with_sign_of_divisor(b.saturating_rem_euclid(&a), &a, config),
+        }),
+        ArithmeticPolicy::Ignore | ArithmeticPolicy::Halt => None,
+    };
+    match result {
+        Some(result) => {
+            if verbose_logging {
+                eprintln!(
+                    "execute {}({}, {}) = {} after an overflow (overflow policy: {})",
+                    command, b, a, result, policy
+                );
+            }
+            stack.push(result);
+        }
+        None => {
+            if verbose_logging {
+                eprintln!(
+                    "skip executing {}({}, {}) due to an overflow (overflow policy: {})",
+                    command, b, a, policy
+                );
+            }
+            let operation = format!("{}({}, {})", command, b, a);
+            stack.push(b);
+            stack.push(a);
+            if policy == ArithmeticPolicy::Halt {
+                return Err(RpietError::ArithmeticOverflow(operation));
+            }
+        }
+    }
+    Ok(())
+}
+
+// the result of MOD takes the sign of the divisor, e.g. 5 mod -3 is -1,
+// while the Euclidean remainder rpiet <= 0.3 used is never negative, see `Compat`
+fn with_sign_of_divisor<N: Number>(remainder: N, divisor: &N, config: &Config) -> N {
+    if config.compat.gives_mod_the_sign_of_the_divisor()
+        && *divisor < N::from_i64(0)
+        && !remainder.is_zero()
+    {
+        // between the divisor and zero, so it cannot overflow
+        remainder.wrapping_add(divisor)
+    } else {
+        remainder
     }
 }
 
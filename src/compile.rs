@@ -0,0 +1,1243 @@
+use std::fmt::{self, Write};
+
+use crate::command::Command;
+use crate::config::Compat;
+use crate::interpreter::Interpreter;
+use crate::number::Number;
+use crate::state_graph::{Location, StateGraph};
+use crate::transition::{pointer_index, POINTER_STATES};
+
+/*
+ * The languages `compile` can translate a Piet image to.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompileTarget {
+    C,
+    Rust,
+}
+
+impl fmt::Display for CompileTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileTarget::C => write!(f, "c"),
+            CompileTarget::Rust => write!(f, "rust"),
+        }
+    }
+}
+
+// images with more states than this get a table of transitions instead of one
+// `switch`/`match` case per state, compilers take minutes for such large functions
+const SWITCH_STATE_LIMIT: usize = 1024;
+
+/// Translates the image an interpreter was created for into a standalone C or Rust program.
+///
+/// The program is a state machine with one state per reachable location (color block or
+/// white codel) and DP/CC combination. It behaves like the interpreter with the default
+/// `Config`: 64 bit numbers which skip overflowing commands, byte-wise IN_NUMBER and
+/// UTF-8 decoding IN_CHAR, and no step limit. Only the `Compat` preset is taken from
+/// the interpreter's configuration.
+///
+/// Up to 1024 states every state is a case of a `switch` (or `match`) with a comment on what
+/// it does. Larger images, like `sample_images/cowsay.png` with over 33 000 states, get a table
+/// with one line per location instead, which a loop looks up; compilers would take minutes for
+/// a `switch` that size.
+pub fn compile<N: Number>(
+    interpreter: &Interpreter<N>,
+    target: CompileTarget,
+    source_name: &str,
+) -> String {
+    let graph = StateGraph::new(interpreter);
+    let mut commands = graph
+        .edges
+        .iter()
+        .flatten()
+        .flatten()
+        .filter_map(|edge| edge.transition.command)
+        .collect::<Vec<_>>();
+    commands.sort_by_key(|command| command.to_string());
+    commands.dedup();
+
+    let mut code = String::new();
+    let (header, helpers, operations) = match target {
+        CompileTarget::C => (C_HEADER, C_HELPERS, C_OPERATIONS),
+        CompileTarget::Rust => (RUST_HEADER, RUST_HELPERS, RUST_OPERATIONS),
+    };
+    code.push_str(&header.replace("{source}", source_name));
+    code.push_str(helpers);
+    for (command, operation) in operations.iter() {
+        if commands.contains(command) {
+            code.push_str(&with_compat(operation, target, graph.compat));
+        }
+    }
+    if graph.locations.len() * POINTER_STATES.len() <= SWITCH_STATE_LIMIT {
+        write_switch(&mut code, interpreter, &graph, target);
+    } else {
+        write_table(&mut code, interpreter, &graph, target, &commands);
+    }
+    code
+}
+
+/*
+ * What the machine does in one state: toggle DP or CC because the way is blocked,
+ * end the program in a trap of white codels, or move to another location with new
+ * DP and CC, either executing a command or toggling after a slide which stopped in
+ * white (`Compat::Rpiet`).
+ */
+enum Step {
+    Blocked,
+    Trapped,
+    Move {
+        location: usize,
+        pointer: usize,
+        toggle: bool,
+        command: Option<Command>,
+        block_size: usize,
+    },
+}
+
+fn step_of<N: Number>(
+    interpreter: &Interpreter<N>,
+    graph: &StateGraph,
+    location: usize,
+    pointer: usize,
+) -> Step {
+    let edge = match graph.edges[location][pointer] {
+        None => return Step::Blocked,
+        Some(edge) => edge,
+    };
+    let transition = edge.transition;
+    if !transition.reached_new_block && graph.compat.slides_turn_in_white() {
+        return Step::Trapped;
+    }
+    let block_size = match graph.locations[location] {
+        Location::Block(block_index) => interpreter.blocks()[block_index].size(),
+        Location::Codel(_) => 0,
+    };
+    Step::Move {
+        location: edge.target,
+        pointer: pointer_index(transition.dp, transition.cc),
+        toggle: !transition.reached_new_block,
+        command: transition.command.filter(|_| transition.reached_new_block),
+        block_size,
+    }
+}
+
+// one case per state, commented with what it does
+fn write_switch<N: Number>(
+    code: &mut String,
+    interpreter: &Interpreter<N>,
+    graph: &StateGraph,
+    target: CompileTarget,
+) {
+    code.push_str(match target {
+        CompileTarget::C => C_MAIN,
+        CompileTarget::Rust => RUST_MAIN,
+    });
+    for (index, location) in graph.locations.iter().enumerate() {
+        let _ = writeln!(code, "{}", comment(target, &location.describe(interpreter)));
+        for (pointer, &(dp, cc)) in POINTER_STATES.iter().enumerate() {
+            let state = index * POINTER_STATES.len() + pointer;
+            let mut statements = Vec::new();
+            let description = match step_of(interpreter, graph, index, pointer) {
+                Step::Blocked => {
+                    statements.push(call(target, "toggle"));
+                    statements.push(increment_toggles(target));
+                    String::from("blocked, toggle")
+                }
+                Step::Trapped => {
+                    statements.push(end_program(target));
+                    String::from("trapped in white, the program ends")
+                }
+                Step::Move {
+                    location: next_location,
+                    pointer: new_pointer,
+                    toggle,
+                    command,
+                    block_size,
+                } => {
+                    statements.push(assign_location(next_location));
+                    statements.push(reset_toggles(target, graph.compat));
+                    let target_description = graph.locations[next_location].describe(interpreter);
+                    if new_pointer != pointer {
+                        statements.push(assign_pointers(target, new_pointer));
+                    }
+                    if toggle {
+                        statements.push(call(target, "toggle"));
+                        format!("slide to {}, toggle", target_description)
+                    } else if let Some(command) = command {
+                        statements.push(execute(target, command, block_size));
+                        format!("{} to {}", command, target_description)
+                    } else {
+                        format!("slide to {}", target_description)
+                    }
+                }
+            };
+            let _ = match target {
+                CompileTarget::C => writeln!(
+                    code,
+                    "        case {}: /* dp={}, cc={}: {} */\n            {}\n            break;",
+                    state,
+                    dp,
+                    cc,
+                    description,
+                    statements.join(" ")
+                ),
+                CompileTarget::Rust => writeln!(
+                    code,
+                    "            {} => {{\n                // dp={}, cc={}: {}\n                {}\n            }}",
+                    state,
+                    dp,
+                    cc,
+                    description,
+                    statements.join(" ")
+                ),
+            };
+        }
+    }
+    code.push_str(match target {
+        CompileTarget::C => C_FOOTER,
+        CompileTarget::Rust => RUST_FOOTER,
+    });
+}
+
+// a table with the transitions of all states and a loop which looks them up,
+// `commands` are the commands the image uses
+fn write_table<N: Number>(
+    code: &mut String,
+    interpreter: &Interpreter<N>,
+    graph: &StateGraph,
+    target: CompileTarget,
+    commands: &[Command],
+) {
+    // commands are numbered after their position in `C_OPERATIONS`, 0 is no command and 1 PUSH
+    let command_number = |command: Command| match command {
+        Command::Push => 1,
+        _ => {
+            2 + C_OPERATIONS
+                .iter()
+                .position(|&(operation, _)| operation == command)
+                .unwrap()
+        }
+    };
+    let mut execute = String::new();
+    for &(command, _) in C_OPERATIONS.iter() {
+        if commands.contains(&command) {
+            let name = format!("op_{}", command.to_string().to_lowercase());
+            let _ = match target {
+                CompileTarget::C => writeln!(
+                    execute,
+                    "    case {}: {}(); break;",
+                    command_number(command),
+                    name
+                ),
+                CompileTarget::Rust => writeln!(
+                    execute,
+                    "            {} => self.{}(),",
+                    command_number(command),
+                    name
+                ),
+            };
+        }
+    }
+    code.push_str(
+        &match target {
+            CompileTarget::C => C_TABLE_EXECUTE,
+            CompileTarget::Rust => RUST_TABLE_EXECUTE,
+        }
+        .replace("{operations}", &execute),
+    );
+
+    let _ = match target {
+        CompileTarget::C => writeln!(
+            code,
+            "static const struct transition transitions[{}] = {{",
+            graph.locations.len() * POINTER_STATES.len()
+        ),
+        CompileTarget::Rust => writeln!(
+            code,
+            "static TRANSITIONS: [Transition; {}] = [",
+            graph.locations.len() * POINTER_STATES.len()
+        ),
+    };
+    for (index, location) in graph.locations.iter().enumerate() {
+        let transitions = (0..POINTER_STATES.len())
+            .map(|pointer| {
+                let (action, location, pointer, command, value) =
+                    match step_of(interpreter, graph, index, pointer) {
+                        Step::Blocked => (0, 0, 0, 0, 0),
+                        Step::Trapped => (1, 0, 0, 0, 0),
+                        Step::Move {
+                            location,
+                            pointer,
+                            toggle,
+                            command,
+                            block_size,
+                        } => (
+                            if toggle { 3 } else { 2 },
+                            location,
+                            pointer,
+                            command.map_or(0, command_number),
+                            block_size,
+                        ),
+                    };
+                match target {
+                    CompileTarget::C => format!(
+                        "{{{}, {}, {}, {}, {}}}",
+                        action, location, pointer, command, value
+                    ),
+                    CompileTarget::Rust => format!(
+                        "({}, {}, {}, {}, {})",
+                        action, location, pointer, command, value
+                    ),
+                }
+            })
+            .collect::<Vec<_>>();
+        let _ = match target {
+            CompileTarget::C => writeln!(
+                code,
+                "    /* {}: {} */\n    {},",
+                index,
+                location.describe(interpreter),
+                transitions.join(", ")
+            ),
+            CompileTarget::Rust => writeln!(
+                code,
+                "    // {}: {}\n    {},",
+                index,
+                location.describe(interpreter),
+                transitions.join(", ")
+            ),
+        };
+    }
+    code.push_str(
+        &match target {
+            CompileTarget::C => C_TABLE_MAIN,
+            CompileTarget::Rust => RUST_TABLE_MAIN,
+        }
+        .replace("{reset_toggles}", &reset_toggles(target, graph.compat)),
+    );
+}
+
+// fills in how an operation handles the corner cases listed at `Compat`
+fn with_compat(operation: &str, target: CompileTarget, compat: Compat) -> String {
+    let keep_values = compat.keeps_values_of_division_by_zero();
+    let drop_negative_depth = !compat.keeps_values_of_negative_roll();
+    let sign_of_divisor = compat.gives_mod_the_sign_of_the_divisor();
+    let odd = if compat.switches_for_negative_odd_values() {
+        "!= 0"
+    } else {
+        "== 1"
+    };
+    let mod_sign = match target {
+        CompileTarget::C if sign_of_divisor => {
+            "    /* the result takes the sign of the divisor */\n    if (result != 0 && (result < 0) != (a < 0)) {\n        result += a;\n    }\n"
+        }
+        CompileTarget::C => {
+            "    /* the result is never negative */\n    if (result < 0) {\n        result = a < 0 ? result - a : result + a;\n    }\n"
+        }
+        CompileTarget::Rust if sign_of_divisor => {
+            "                // the result takes the sign of the divisor\n                let result = b\n                    .checked_rem_euclid(a)\n                    .map(|result| if a < 0 && result != 0 { result + a } else { result });\n"
+        }
+        CompileTarget::Rust => {
+            "                // the result is never negative\n                let result = b.checked_rem_euclid(a);\n"
+        }
+    };
+    let (division_by_zero, negative_roll_depth) = match target {
+        CompileTarget::C => (
+            if keep_values {
+                "/* dividing by zero keeps both values */"
+            } else {
+                "/* dividing by zero drops both values */\n        stack_len -= 2;"
+            },
+            if drop_negative_depth {
+                "    if (depth < 0) {\n        /* a negative depth drops both values */\n        stack_len -= 2;\n        return;\n    }\n"
+            } else {
+                ""
+            },
+        ),
+        CompileTarget::Rust => (
+            if keep_values {
+                "// dividing by zero keeps both values\n                self.stack.extend_from_slice(&[b, a]);"
+            } else {
+                "// dividing by zero drops both values"
+            },
+            if drop_negative_depth {
+                "            // a negative depth drops both values\n            if depth < 0 {\n                return;\n            }\n"
+            } else {
+                ""
+            },
+        ),
+    };
+    operation
+        .replace("{division_by_zero}", division_by_zero)
+        .replace("{negative_roll_depth}", negative_roll_depth)
+        .replace("{mod_sign}", mod_sign)
+        .replace("{odd}", odd)
+}
+
+fn comment(target: CompileTarget, text: &str) -> String {
+    match target {
+        CompileTarget::C => format!("        /* {} */", text),
+        CompileTarget::Rust => format!("            // {}", text),
+    }
+}
+
+fn call(target: CompileTarget, function: &str) -> String {
+    match target {
+        CompileTarget::C => format!("{}();", function),
+        CompileTarget::Rust => format!("machine.{}();", function),
+    }
+}
+
+// the same statement in both languages
+fn assign_location(location: usize) -> String {
+    format!("location = {};", location)
+}
+
+// after a move the next blocked move starts over with toggling CC, unless `Compat` says otherwise
+fn reset_toggles(target: CompileTarget, compat: Compat) -> String {
+    let restart = compat.restarts_toggling_after_move();
+    match target {
+        CompileTarget::C if restart => String::from("toggles = 0; toggle_dp_next = 0;"),
+        CompileTarget::C => String::from("toggles = 0;"),
+        CompileTarget::Rust if restart => {
+            String::from("machine.toggles = 0; machine.toggle_dp_next = false;")
+        }
+        CompileTarget::Rust => String::from("machine.toggles = 0;"),
+    }
+}
+
+// slides through white may turn DP and CC, `pointer` numbers them like the states of the machine
+fn assign_pointers(target: CompileTarget, pointer: usize) -> String {
+    match target {
+        CompileTarget::C => format!("dp = {}; cc = {};", pointer / 2, pointer % 2),
+        CompileTarget::Rust => format!(
+            "machine.dp = {}; machine.cc = {};",
+            pointer / 2,
+            pointer % 2
+        ),
+    }
+}
+
+// the machines stop once DP and CC got toggled eight times in a row
+fn end_program(target: CompileTarget) -> String {
+    match target {
+        CompileTarget::C => String::from("toggles = 8;"),
+        CompileTarget::Rust => String::from("machine.toggles = 8;"),
+    }
+}
+
+fn increment_toggles(target: CompileTarget) -> String {
+    match target {
+        CompileTarget::C => String::from("toggles++;"),
+        CompileTarget::Rust => String::from("machine.toggles += 1;"),
+    }
+}
+
+fn execute(target: CompileTarget, command: Command, block_size: usize) -> String {
+    if command == Command::Push {
+        return match target {
+            CompileTarget::C => format!("push({});", block_size),
+            CompileTarget::Rust => format!("machine.stack.push({});", block_size),
+        };
+    }
+    call(
+        target,
+        &format!("op_{}", command.to_string().to_lowercase()),
+    )
+}
+
+const C_HEADER: &str = r#"/*
+ * Generated by rpiet from {source}.
+ * Build with e.g. `cc -O2 -o program program.c` (needs GCC or Clang for the overflow checks).
+ *
+ * Numbers on the stack are 64 bit integers, commands which would overflow are skipped.
+ */
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static int64_t *stack = NULL;
+static size_t stack_len = 0;
+static size_t stack_capacity = 0;
+/* DP and CC are numbered like the states of the machine in main: location * 8 + dp * 2 + cc */
+static int dp = 1; /* 0 = up, 1 = right, 2 = down, 3 = left */
+static int cc = 0; /* 0 = left, 1 = right */
+static int toggles = 0; /* pointer toggles without moving, the program ends at 8 */
+static int toggle_dp_next = 0; /* toggling alternates between CC and DP */
+
+"#;
+
+const C_HELPERS: &str = r#"static inline void push(int64_t value) {
+    if (stack_len == stack_capacity) {
+        stack_capacity = stack_capacity ? stack_capacity * 2 : 64;
+        stack = realloc(stack, stack_capacity * sizeof(int64_t));
+        if (!stack) {
+            perror("rpiet");
+            exit(1);
+        }
+    }
+    stack[stack_len++] = value;
+}
+
+static inline void toggle(void) {
+    if (toggle_dp_next) {
+        dp = (dp + 1) % 4;
+    } else {
+        cc ^= 1;
+    }
+    toggle_dp_next = !toggle_dp_next;
+}
+
+static inline int peek_byte(void) {
+    int byte = getchar();
+    if (byte != EOF) {
+        ungetc(byte, stdin);
+    }
+    return byte;
+}
+
+static inline void reverse(int64_t *values, size_t length) {
+    for (size_t i = 0; i < length / 2; i++) {
+        int64_t value = values[i];
+        values[i] = values[length - 1 - i];
+        values[length - 1 - i] = value;
+    }
+}
+
+"#;
+
+const C_OPERATIONS: [(Command, &str); 16] = [
+    (
+        Command::Pop,
+        r#"static void op_pop(void) {
+    if (stack_len >= 1) {
+        stack_len--;
+    }
+}
+
+"#,
+    ),
+    (
+        Command::Add,
+        r#"static void op_add(void) {
+    int64_t result;
+    if (stack_len < 2 || __builtin_add_overflow(stack[stack_len - 2], stack[stack_len - 1], &result)) {
+        return;
+    }
+    stack_len -= 2;
+    push(result);
+}
+
+"#,
+    ),
+    (
+        Command::Subtract,
+        r#"static void op_subtract(void) {
+    int64_t result;
+    if (stack_len < 2 || __builtin_sub_overflow(stack[stack_len - 2], stack[stack_len - 1], &result)) {
+        return;
+    }
+    stack_len -= 2;
+    push(result);
+}
+
+"#,
+    ),
+    (
+        Command::Multiply,
+        r#"static void op_multiply(void) {
+    int64_t result;
+    if (stack_len < 2 || __builtin_mul_overflow(stack[stack_len - 2], stack[stack_len - 1], &result)) {
+        return;
+    }
+    stack_len -= 2;
+    push(result);
+}
+
+"#,
+    ),
+    (
+        Command::Divide,
+        r#"static void op_divide(void) {
+    if (stack_len < 2) {
+        return;
+    }
+    int64_t a = stack[stack_len - 1];
+    int64_t b = stack[stack_len - 2];
+    if (a == 0) {
+        {division_by_zero}
+        return;
+    }
+    if (a == -1 && b == INT64_MIN) {
+        return;
+    }
+    stack_len -= 2;
+    push(b / a);
+}
+
+"#,
+    ),
+    (
+        Command::Mod,
+        r#"static void op_mod(void) {
+    if (stack_len < 2) {
+        return;
+    }
+    int64_t a = stack[stack_len - 1];
+    int64_t b = stack[stack_len - 2];
+    if (a == 0) {
+        {division_by_zero}
+        return;
+    }
+    if (a == -1 && b == INT64_MIN) {
+        return;
+    }
+    int64_t result = b % a;
+{mod_sign}    stack_len -= 2;
+    push(result);
+}
+
+"#,
+    ),
+    (
+        Command::Not,
+        r#"static void op_not(void) {
+    if (stack_len >= 1) {
+        stack[stack_len - 1] = stack[stack_len - 1] == 0;
+    }
+}
+
+"#,
+    ),
+    (
+        Command::Greater,
+        r#"static void op_greater(void) {
+    if (stack_len < 2) {
+        return;
+    }
+    int64_t a = stack[stack_len - 1];
+    int64_t b = stack[stack_len - 2];
+    stack_len -= 2;
+    push(b > a);
+}
+
+"#,
+    ),
+    (
+        Command::Pointer,
+        r#"static void op_pointer(void) {
+    if (stack_len >= 1) {
+        int64_t a = stack[--stack_len];
+        dp = (int)((dp + (a % 4 + 4) % 4) % 4);
+    }
+}
+
+"#,
+    ),
+    (
+        Command::Switch,
+        r#"static void op_switch(void) {
+    if (stack_len >= 1 && stack[--stack_len] % 2 {odd}) {
+        cc ^= 1;
+    }
+}
+
+"#,
+    ),
+    (
+        Command::Duplicate,
+        r#"static void op_duplicate(void) {
+    if (stack_len >= 1) {
+        push(stack[stack_len - 1]);
+    }
+}
+
+"#,
+    ),
+    (
+        Command::Roll,
+        r#"static void op_roll(void) {
+    if (stack_len < 2) {
+        return;
+    }
+    int64_t rolls = stack[stack_len - 1];
+    int64_t depth = stack[stack_len - 2];
+{negative_roll_depth}    if (depth <= 0 || (int64_t)(stack_len - 2) < depth) {
+        return;
+    }
+    stack_len -= 2;
+    size_t length = (size_t)depth;
+    rolls %= depth;
+    /* rolling is a right rotation of the top `depth` values */
+    size_t shift = rolls >= 0 ? (size_t)rolls : (length - (size_t)(-rolls)) % length;
+    int64_t *values = stack + stack_len - length;
+    reverse(values, length);
+    reverse(values, shift);
+    reverse(values + shift, length - shift);
+}
+
+"#,
+    ),
+    (
+        Command::InNumber,
+        r#"static void op_in_number(void) {
+    fflush(stdout);
+    int byte = peek_byte();
+    while (byte == ' ' || byte == '\t' || byte == '\n' || byte == '\f' || byte == '\r') {
+        getchar();
+        byte = peek_byte();
+    }
+    int negative = 0;
+    if (byte == '+' || byte == '-') {
+        negative = byte == '-';
+        getchar();
+    }
+    uint64_t magnitude = 0;
+    int digits = 0;
+    int overflow = 0;
+    while ((byte = peek_byte()) != EOF && byte >= '0' && byte <= '9') {
+        getchar();
+        digits = 1;
+        if (magnitude > (UINT64_C(9223372036854775808) - (uint64_t)(byte - '0')) / 10) {
+            overflow = 1;
+        } else {
+            magnitude = magnitude * 10 + (uint64_t)(byte - '0');
+        }
+    }
+    if (!digits || overflow || (!negative && magnitude > INT64_MAX)) {
+        return;
+    }
+    push(negative ? -(int64_t)(magnitude - 1) - 1 : (int64_t)magnitude);
+}
+
+"#,
+    ),
+    (
+        Command::InChar,
+        r#"static void op_in_char(void) {
+    fflush(stdout);
+    int first = getchar();
+    if (first == EOF) {
+        return;
+    }
+    int length = first <= 0x7F ? 1
+        : first >= 0xC2 && first <= 0xDF ? 2
+        : first >= 0xE0 && first <= 0xEF ? 3
+        : first >= 0xF0 && first <= 0xF4 ? 4
+        : 0;
+    if (length == 0) {
+        push(0xFFFD);
+        return;
+    }
+    int bytes[4] = {first, 0, 0, 0};
+    int count = 1;
+    while (count < length) {
+        int byte = peek_byte();
+        if (byte == EOF || (byte & 0xC0) != 0x80) {
+            break;
+        }
+        bytes[count++] = getchar();
+    }
+    /* truncated sequences, overlong encodings, surrogates and values above U+10FFFF read as U+FFFD */
+    if (count < length
+        || (first == 0xE0 && bytes[1] < 0xA0) || (first == 0xED && bytes[1] > 0x9F)
+        || (first == 0xF0 && bytes[1] < 0x90) || (first == 0xF4 && bytes[1] > 0x8F)) {
+        push(0xFFFD);
+        return;
+    }
+    int64_t code_point = length == 1 ? first : first & (0x7F >> length);
+    for (int i = 1; i < length; i++) {
+        code_point = (code_point << 6) | (bytes[i] & 0x3F);
+    }
+    push(code_point);
+}
+
+"#,
+    ),
+    (
+        Command::OutNumber,
+        r#"static void op_out_number(void) {
+    if (stack_len >= 1) {
+        printf("%" PRId64, stack[--stack_len]);
+    }
+}
+
+"#,
+    ),
+    (
+        Command::OutChar,
+        r#"static void op_out_char(void) {
+    if (stack_len < 1) {
+        return;
+    }
+    int64_t c = stack[stack_len - 1];
+    if (c < 0 || c > 0x10FFFF || (c >= 0xD800 && c <= 0xDFFF)) {
+        /* not a valid character, it stays on the stack */
+        return;
+    }
+    stack_len--;
+    if (c < 0x80) {
+        putchar((int)c);
+    } else if (c < 0x800) {
+        putchar((int)(0xC0 | (c >> 6)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    } else if (c < 0x10000) {
+        putchar((int)(0xE0 | (c >> 12)));
+        putchar((int)(0x80 | ((c >> 6) & 0x3F)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    } else {
+        putchar((int)(0xF0 | (c >> 18)));
+        putchar((int)(0x80 | ((c >> 12) & 0x3F)));
+        putchar((int)(0x80 | ((c >> 6) & 0x3F)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    }
+}
+
+"#,
+    ),
+];
+
+const C_MAIN: &str = r#"int main(void) {
+    /* like rpiet, show output of programs which never end */
+    setvbuf(stdout, NULL, _IOLBF, 0);
+    int location = 0;
+    while (toggles < 8) {
+        switch (location * 8 + dp * 2 + cc) {
+"#;
+
+const C_FOOTER: &str = r#"        }
+    }
+    fflush(stdout);
+    return 0;
+}
+"#;
+
+const RUST_HEADER: &str = r#"// Generated by rpiet from {source}.
+// Build with e.g. `rustc -O program.rs`.
+//
+// Numbers on the stack are 64 bit integers, commands which would overflow are skipped.
+#![allow(dead_code)]
+
+use std::io::{self, BufRead, BufReader, Stdin, Stdout, Write};
+
+struct Machine {
+    stack: Vec<i64>,
+    dp: usize,             // 0 = up, 1 = right, 2 = down, 3 = left
+    cc: usize,             // 0 = left, 1 = right
+    toggles: u8,           // pointer toggles without moving, the program ends at 8
+    toggle_dp_next: bool,  // toggling alternates between CC and DP
+    input: BufReader<Stdin>,
+    output: Stdout,        // line buffered, shows the output of programs which never end
+}
+
+"#;
+
+const RUST_HELPERS: &str = r#"impl Machine {
+    fn new() -> Machine {
+        Machine {
+            stack: Vec::with_capacity(64),
+            dp: 1,
+            cc: 0,
+            toggles: 0,
+            toggle_dp_next: false,
+            input: BufReader::new(io::stdin()),
+            output: io::stdout(),
+        }
+    }
+
+    fn toggle(&mut self) {
+        if self.toggle_dp_next {
+            self.dp = (self.dp + 1) % 4;
+        } else {
+            self.cc ^= 1;
+        }
+        self.toggle_dp_next = !self.toggle_dp_next;
+    }
+
+    fn peek_byte(&mut self) -> Option<u8> {
+        self.input.fill_buf().ok().and_then(|buffer| buffer.first().cloned())
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.peek_byte();
+        if byte.is_some() {
+            self.input.consume(1);
+        }
+        byte
+    }
+
+    fn pop2(&mut self) -> Option<(i64, i64)> {
+        if self.stack.len() < 2 {
+            return None;
+        }
+        let a = self.stack.pop().unwrap();
+        let b = self.stack.pop().unwrap();
+        Some((a, b))
+    }
+
+    // pushes the result, or restores both values if the operation overflowed
+    fn push_checked(&mut self, a: i64, b: i64, result: Option<i64>) {
+        match result {
+            Some(result) => self.stack.push(result),
+            None => {
+                self.stack.push(b);
+                self.stack.push(a);
+            }
+        }
+    }
+
+"#;
+
+const RUST_OPERATIONS: [(Command, &str); 16] = [
+    (
+        Command::Pop,
+        r#"    fn op_pop(&mut self) {
+        self.stack.pop();
+    }
+
+"#,
+    ),
+    (
+        Command::Add,
+        r#"    fn op_add(&mut self) {
+        if let Some((a, b)) = self.pop2() {
+            self.push_checked(a, b, b.checked_add(a));
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::Subtract,
+        r#"    fn op_subtract(&mut self) {
+        if let Some((a, b)) = self.pop2() {
+            self.push_checked(a, b, b.checked_sub(a));
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::Multiply,
+        r#"    fn op_multiply(&mut self) {
+        if let Some((a, b)) = self.pop2() {
+            self.push_checked(a, b, b.checked_mul(a));
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::Divide,
+        r#"    fn op_divide(&mut self) {
+        if let Some((a, b)) = self.pop2() {
+            if a != 0 {
+                self.push_checked(a, b, b.checked_div(a));
+            } else {
+                {division_by_zero}
+            }
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::Mod,
+        r#"    fn op_mod(&mut self) {
+        if let Some((a, b)) = self.pop2() {
+            if a != 0 {
+{mod_sign}                self.push_checked(a, b, result);
+            } else {
+                {division_by_zero}
+            }
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::Not,
+        r#"    fn op_not(&mut self) {
+        if let Some(a) = self.stack.pop() {
+            self.stack.push((a == 0) as i64);
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::Greater,
+        r#"    fn op_greater(&mut self) {
+        if let Some((a, b)) = self.pop2() {
+            self.stack.push((b > a) as i64);
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::Pointer,
+        r#"    fn op_pointer(&mut self) {
+        if let Some(a) = self.stack.pop() {
+            self.dp = (self.dp + (a % 4 + 4) as usize % 4) % 4;
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::Switch,
+        r#"    fn op_switch(&mut self) {
+        if let Some(a) = self.stack.pop() {
+            if a % 2 {odd} {
+                self.cc ^= 1;
+            }
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::Duplicate,
+        r#"    fn op_duplicate(&mut self) {
+        if let Some(&last) = self.stack.last() {
+            self.stack.push(last);
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::Roll,
+        r#"    fn op_roll(&mut self) {
+        if let Some((rolls, depth)) = self.pop2() {
+{negative_roll_depth}            if depth <= 0 || (self.stack.len() as i64) < depth {
+                self.stack.push(depth);
+                self.stack.push(rolls);
+                return;
+            }
+            let rolls = rolls % depth;
+            let start = self.stack.len() - depth as usize;
+            if rolls > 0 {
+                self.stack[start..].rotate_right(rolls as usize);
+            } else {
+                self.stack[start..].rotate_left(-rolls as usize);
+            }
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::InNumber,
+        r#"    fn op_in_number(&mut self) {
+        let _ = self.output.flush();
+        while let Some(byte) = self.peek_byte() {
+            if !byte.is_ascii_whitespace() {
+                break;
+            }
+            self.input.consume(1);
+        }
+        let mut text = String::new();
+        if let Some(sign @ b'+') | Some(sign @ b'-') = self.peek_byte() {
+            text.push(sign as char);
+            self.input.consume(1);
+        }
+        while let Some(digit) = self.peek_byte() {
+            if !digit.is_ascii_digit() {
+                break;
+            }
+            text.push(digit as char);
+            self.input.consume(1);
+        }
+        if let Ok(number) = text.parse::<i64>() {
+            self.stack.push(number);
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::InChar,
+        r#"    fn op_in_char(&mut self) {
+        let _ = self.output.flush();
+        let first = match self.read_byte() {
+            Some(first) => first,
+            None => return,
+        };
+        let length = match first {
+            0x00..=0x7F => 1,
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => 0,
+        };
+        let mut bytes = vec![first];
+        while bytes.len() < length {
+            match self.peek_byte() {
+                Some(byte) if byte & 0xC0 == 0x80 => {
+                    bytes.push(byte);
+                    self.input.consume(1);
+                }
+                _ => break,
+            }
+        }
+        // invalid or truncated sequences read as U+FFFD
+        let c = std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|text| text.chars().next())
+            .unwrap_or('\u{FFFD}');
+        self.stack.push(c as i64);
+    }
+
+"#,
+    ),
+    (
+        Command::OutNumber,
+        r#"    fn op_out_number(&mut self) {
+        if let Some(a) = self.stack.pop() {
+            let _ = write!(self.output, "{}", a);
+        }
+    }
+
+"#,
+    ),
+    (
+        Command::OutChar,
+        r#"    fn op_out_char(&mut self) {
+        // values which are not valid characters stay on the stack
+        if let Some(&a) = self.stack.last() {
+            let c = if a >= 0 && a <= 0x10FFFF { std::char::from_u32(a as u32) } else { None };
+            if let Some(c) = c {
+                self.stack.pop();
+                let _ = write!(self.output, "{}", c);
+            }
+        }
+    }
+
+"#,
+    ),
+];
+
+const RUST_MAIN: &str = r#"}
+
+fn main() {
+    let mut machine = Machine::new();
+    let mut location = 0;
+    while machine.toggles < 8 {
+        match location * 8 + machine.dp * 2 + machine.cc {
+"#;
+
+const RUST_FOOTER: &str = r#"            _ => unreachable!(),
+        }
+    }
+    let _ = machine.output.flush();
+}
+"#;
+
+const C_TABLE_EXECUTE: &str = r#"/*
+ * What happens in each state, numbered like the states of the loop in main: location * 8 + dp * 2 + cc.
+ * action: 0 = blocked, toggle; 1 = trapped in white, the program ends;
+ *         2 = move to `location` and execute `command`; 3 = move to `location` and toggle
+ * pointer: dp * 2 + cc after the move
+ * command: 0 = none, 1 = push `value`, otherwise an operation of `execute`
+ */
+struct transition {
+    unsigned char action;
+    unsigned int location;
+    unsigned char pointer;
+    unsigned char command;
+    unsigned int value;
+};
+
+static void execute(int command, int64_t value) {
+    switch (command) {
+    case 1: push(value); break;
+{operations}    }
+}
+
+"#;
+
+const C_TABLE_MAIN: &str = r#"};
+
+int main(void) {
+    /* like rpiet, show output of programs which never end */
+    setvbuf(stdout, NULL, _IOLBF, 0);
+    unsigned int location = 0;
+    while (toggles < 8) {
+        const struct transition *transition = &transitions[location * 8 + dp * 2 + cc];
+        switch (transition->action) {
+        case 0:
+            toggle();
+            toggles++;
+            break;
+        case 1:
+            toggles = 8;
+            break;
+        default:
+            location = transition->location;
+            {reset_toggles}
+            dp = transition->pointer / 2;
+            cc = transition->pointer % 2;
+            if (transition->action == 3) {
+                toggle();
+            } else {
+                execute(transition->command, transition->value);
+            }
+        }
+    }
+    fflush(stdout);
+    return 0;
+}
+"#;
+
+const RUST_TABLE_EXECUTE: &str = r#"    fn execute(&mut self, command: u8, value: u32) {
+        match command {
+            1 => self.stack.push(value as i64),
+{operations}            _ => (),
+        }
+    }
+}
+
+// What happens in each state, numbered like the states of the loop in main: location * 8 + dp * 2 + cc.
+// The fields are (action, location, pointer, command, value):
+// action: 0 = blocked, toggle; 1 = trapped in white, the program ends;
+//         2 = move to `location` and execute `command`; 3 = move to `location` and toggle
+// pointer: dp * 2 + cc after the move
+// command: 0 = none, 1 = push `value`, otherwise an operation of `Machine::execute`
+type Transition = (u8, u32, u8, u8, u32);
+
+"#;
+
+const RUST_TABLE_MAIN: &str = r#"];
+
+fn main() {
+    let mut machine = Machine::new();
+    let mut location = 0;
+    while machine.toggles < 8 {
+        let (action, next_location, pointer, command, value) =
+            TRANSITIONS[location * 8 + machine.dp * 2 + machine.cc];
+        match action {
+            0 => {
+                machine.toggle();
+                machine.toggles += 1;
+            }
+            1 => machine.toggles = 8,
+            _ => {
+                location = next_location as usize;
+                {reset_toggles}
+                machine.dp = pointer as usize / 2;
+                machine.cc = pointer as usize % 2;
+                if action == 3 {
+                    machine.toggle();
+                } else {
+                    machine.execute(command, value);
+                }
+            }
+        }
+    }
+    let _ = machine.output.flush();
+}
+"#;
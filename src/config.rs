@@ -0,0 +1,172 @@
+use std::fmt;
+
+use crate::palette::Palette;
+
+/*
+ * Config holds all settings of an interpreter run which are independent of
+ * the command line interface. Embedders construct it directly, the binary
+ * builds it from its command line options.
+ */
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub verbose: bool,
+    pub codel_size: CodelSize,
+    pub max_steps: u128,
+    pub unlimited_steps: bool,
+    pub unknown_color: UnknownColor,
+    pub palette: Palette,
+    pub arithmetic_policy: ArithmeticPolicy,
+    // IN_NUMBER reads a whole line and discards what follows the number, like rpiet <= 0.3 did
+    pub line_based_in_number: bool,
+    // IN_CHAR pushes single bytes instead of decoding UTF-8 characters
+    pub raw_byte_in_char: bool,
+    // computes where every block leads for all DP/CC combinations before the first step
+    pub precompute_transitions: bool,
+    pub compat: Compat,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            verbose: false,
+            codel_size: CodelSize::Auto,
+            max_steps: 0,
+            unlimited_steps: true,
+            unknown_color: UnknownColor::White,
+            palette: Palette::standard(),
+            arithmetic_policy: ArithmeticPolicy::Ignore,
+            line_based_in_number: false,
+            raw_byte_in_char: false,
+            precompute_transitions: false,
+            compat: Compat::Spec,
+        }
+    }
+}
+
+/*
+ * The length of a codel in pixels. `Auto` derives it from the image by looking
+ * at the lengths of all same-colored pixel runs.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CodelSize {
+    Auto,
+    Fixed(u32),
+}
+
+/*
+ * What to do when ADD, SUBTRACT, MULTIPLY, DIVIDE or MOD overflow the number
+ * backend, e.g. `i64::MAX + 1` or `i64::MIN / -1`:
+ * ignore the command (like commands which find too few values on the stack),
+ * wrap around, saturate at the numeric bounds, or halt the program with an error.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithmeticPolicy {
+    Ignore,
+    Wrap,
+    Saturate,
+    Halt,
+}
+
+impl fmt::Display for ArithmeticPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArithmeticPolicy::Ignore => write!(f, "ignore"),
+            ArithmeticPolicy::Wrap => write!(f, "wrap"),
+            ArithmeticPolicy::Saturate => write!(f, "saturate"),
+            ArithmeticPolicy::Halt => write!(f, "halt"),
+        }
+    }
+}
+
+/*
+ * How pixels are interpreted whose color is none of the 20 Piet colors:
+ * as white or black codels, as the perceptually nearest Piet color, or not at
+ * all, failing with `RpietError::UnknownColor`.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnknownColor {
+    White,
+    Black,
+    Nearest,
+    Error,
+}
+
+impl fmt::Display for UnknownColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnknownColor::White => write!(f, "white"),
+            UnknownColor::Black => write!(f, "black"),
+            UnknownColor::Nearest => write!(f, "nearest"),
+            UnknownColor::Error => write!(f, "error"),
+        }
+    }
+}
+
+/*
+ * Piet interpreters disagree on a few corner cases which the specification
+ * leaves open or which older interpreters handled differently. A Compat preset
+ * selects the behavior for all of them, so images act like they do under the
+ * interpreter they were written for:
+ *
+ * | corner case                    | Spec                                     | Npiet                          | Rpiet                                 |
+ * |--------------------------------|------------------------------------------|--------------------------------|---------------------------------------|
+ * | a slide through white runs     | toggle CC, turn DP clockwise and slide   | like Spec                      | stop on the last white codel and      |
+ * | into a black codel or an edge  | on, a slide which circles is trapped and |                                | toggle CC or DP like in a color block |
+ * |                                | ends the program                         |                                | (rpiet <= 0.3)                        |
+ * | ROLL with a negative depth     | ignored, depth and rolls stay            | ignored, depth and rolls are   | like Spec                             |
+ * |                                |                                          | popped                         |                                       |
+ * | DIVIDE or MOD by zero          | ignored, both values stay                | like Spec                      | ignored, both values are popped       |
+ * |                                |                                          |                                | (rpiet <= 0.3)                        |
+ * | a blocked move after a move    | toggles CC first                         | like Spec                      | toggles whichever of CC and DP is     |
+ * |                                |                                          |                                | next after the last blocked move      |
+ * |                                |                                          |                                | (rpiet <= 0.3)                        |
+ * | MOD with a negative divisor    | takes the sign of the divisor,           | like Spec                      | never negative, 5 mod -3 is 2         |
+ * |                                | 5 mod -3 is -1                           |                                | (rpiet <= 0.3)                        |
+ * | SWITCH with a negative odd     | toggles CC                               | like Spec                      | keeps CC (rpiet <= 0.3)               |
+ * | value                          |                                          |                                |                                       |
+ *
+ * `tests/npiet.rs` compares the Npiet column with npiet itself when it is installed.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compat {
+    Spec,
+    Npiet,
+    Rpiet,
+}
+
+impl Compat {
+    // slides through white turn at obstacles until they reach a color block or get trapped
+    pub(crate) fn slides_turn_in_white(self) -> bool {
+        self != Compat::Rpiet
+    }
+
+    pub(crate) fn keeps_values_of_negative_roll(self) -> bool {
+        self != Compat::Npiet
+    }
+
+    pub(crate) fn keeps_values_of_division_by_zero(self) -> bool {
+        self != Compat::Rpiet
+    }
+
+    pub(crate) fn restarts_toggling_after_move(self) -> bool {
+        self != Compat::Rpiet
+    }
+
+    pub(crate) fn gives_mod_the_sign_of_the_divisor(self) -> bool {
+        self != Compat::Rpiet
+    }
+
+    pub(crate) fn switches_for_negative_odd_values(self) -> bool {
+        self != Compat::Rpiet
+    }
+}
+
+impl fmt::Display for Compat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Compat::Spec => write!(f, "spec"),
+            Compat::Npiet => write!(f, "npiet"),
+            Compat::Rpiet => write!(f, "rpiet"),
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DirectionPointer {
     Up,
     Right,
@@ -19,7 +19,7 @@ impl fmt::Display for DirectionPointer {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CodelChooser {
     Right,
     Left,
@@ -0,0 +1,532 @@
+use std::cell::RefCell;
+use std::cmp;
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::rc::Rc;
+
+use rpiet::{Command, Interpreter, Number};
+
+const HELP: &str = "\
+Commands:
+  step, s [<N>]            execute a single step, or N steps like `next`
+  next, n <N>              execute N steps (stops early at breakpoints)
+  continue, c              run until a breakpoint, a watch or the end of the program
+  break, b <x>,<y>         break when the interpreter enters the block of codel (x, y)
+  break, b block <N>       break when the interpreter enters block N
+  break, b <command>       break before executing a command, e.g. `break roll`
+  watch, w                 break whenever the stack depth changes
+  watch, w <N>             break when the stack depth becomes N
+  unwatch                  remove the stack depth watch
+  delete, d <N>            delete breakpoint N
+  info, i                  list breakpoints and the watch
+  print, p                 print the current DP, CC, position, block and stack
+  help, h                  show this help
+  quit, q                  stop debugging";
+
+#[derive(Debug, PartialEq)]
+enum Breakpoint {
+    Codel(usize, usize),
+    Block(usize),
+    Command(Command),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Codel(x, y) => write!(f, "codel ({}, {})", x, y),
+            Breakpoint::Block(index) => write!(f, "block #{}", index),
+            Breakpoint::Command(command) => write!(f, "command {}", command),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Watch {
+    DepthChanges,
+    Depth(usize),
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watch::DepthChanges => write!(f, "stack depth changes"),
+            Watch::Depth(depth) => write!(f, "stack depth becomes {}", depth),
+        }
+    }
+}
+
+// what a line typed at the prompt asks for
+#[derive(Debug, PartialEq)]
+enum Action {
+    Step(u128),
+    Continue,
+    Break(Breakpoint),
+    Watch(Watch),
+    Unwatch,
+    Delete(usize),
+    Info,
+    Print,
+    Help,
+    Quit,
+}
+
+/*
+ * The Debugger drives an interpreter step by step with `Interpreter::advance`,
+ * reading commands from stdin and reporting to stderr, so the program's own
+ * output on stdout stays untouched. The program reads its input from the same
+ * `SharedInput`, so commands and input can be piped in together.
+ */
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watch: Option<Watch>,
+    // the step at which a command breakpoint last stopped, resuming there executes the command
+    command_stop_step: Option<u128>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watch: None,
+            command_stop_step: None,
+        }
+    }
+
+    pub fn run<N: Number>(
+        &mut self,
+        interpreter: &mut Interpreter<N>,
+        mut commands: impl BufRead,
+    ) -> io::Result<()> {
+        eprintln!("rpiet debugger, type `help` for a list of commands");
+        print_state(interpreter);
+        loop {
+            io::stdout().flush()?;
+            eprint!("(rpiet) ");
+            let mut line = String::new();
+            if commands.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let action = match parse_action(&line) {
+                Ok(Some(action)) => action,
+                Ok(None) => continue,
+                Err(message) => {
+                    eprintln!("{}", message);
+                    continue;
+                }
+            };
+            match action {
+                Action::Step(steps) => self.advance(interpreter, steps),
+                Action::Continue => self.advance(interpreter, u128::MAX),
+                Action::Break(breakpoint) => {
+                    eprintln!("breakpoint {}: {}", self.breakpoints.len(), breakpoint);
+                    self.breakpoints.push(breakpoint);
+                }
+                Action::Watch(watch) => {
+                    eprintln!("watching: {}", watch);
+                    self.watch = Some(watch);
+                }
+                Action::Unwatch => self.watch = None,
+                Action::Delete(index) if index < self.breakpoints.len() => {
+                    let breakpoint = self.breakpoints.remove(index);
+                    eprintln!("deleted breakpoint {}: {}", index, breakpoint);
+                }
+                Action::Delete(_) => {
+                    eprintln!("usage: delete <N>, see `info` for existing breakpoints")
+                }
+                Action::Info => self.print_info(),
+                Action::Print => print_state(interpreter),
+                Action::Help => eprintln!("{}", HELP),
+                Action::Quit => return Ok(()),
+            }
+        }
+    }
+
+    fn advance<N: Number>(&mut self, interpreter: &mut Interpreter<N>, steps: u128) {
+        let mut steps_done = 0;
+        loop {
+            if !interpreter.is_alive() {
+                eprintln!("the program has ended");
+                return;
+            }
+            // command breakpoints are checked before the step, so they trigger on the first one, too
+            let step = interpreter.step_counter();
+            if self.command_stop_step != Some(step) {
+                if let Some(reason) = self.command_breakpoint_hit(interpreter) {
+                    self.command_stop_step = Some(step);
+                    eprintln!("stopped: {}", reason);
+                    break;
+                }
+            }
+            if steps_done == steps {
+                break;
+            }
+            let position = interpreter.current_position();
+            let stack_depth = interpreter.stack().len();
+            interpreter.advance();
+            steps_done += 1;
+            if let Some(reason) = self.stop_reason(interpreter, position, stack_depth) {
+                eprintln!("stopped: {}", reason);
+                break;
+            }
+        }
+        print_state(interpreter);
+    }
+
+    fn command_breakpoint_hit<N: Number>(&self, interpreter: &Interpreter<N>) -> Option<String> {
+        let next_command = interpreter.next_command()?;
+        self.breakpoints
+            .iter()
+            .position(|breakpoint| *breakpoint == Breakpoint::Command(next_command))
+            .map(|index| format!("breakpoint {} ({})", index, self.breakpoints[index]))
+    }
+
+    fn stop_reason<N: Number>(
+        &self,
+        interpreter: &Interpreter<N>,
+        previous_position: (usize, usize),
+        previous_stack_depth: usize,
+    ) -> Option<String> {
+        if !interpreter.is_alive() {
+            return Some(String::from("the program has ended"));
+        }
+        let position = interpreter.current_position();
+        let block_index = interpreter.block_index_at(position);
+        let previous_block_index = interpreter.block_index_at(previous_position);
+        for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+            // codel and block breakpoints only trigger when arriving, not while toggling pointers in place
+            let hit = match breakpoint {
+                // any codel of a block stands for the whole block, white codels only for themselves
+                Breakpoint::Codel(x, y) => match interpreter.block_index_at((*x, *y)) {
+                    Some(codel_block_index) => {
+                        block_index == Some(codel_block_index)
+                            && previous_block_index != block_index
+                    }
+                    None => position == (*x, *y) && previous_position != position,
+                },
+                Breakpoint::Block(index) => {
+                    block_index == Some(*index) && previous_block_index != block_index
+                }
+                // checked before each step by `command_breakpoint_hit`
+                Breakpoint::Command(_) => false,
+            };
+            if hit {
+                return Some(format!("breakpoint {} ({})", index, breakpoint));
+            }
+        }
+        let stack_depth = interpreter.stack().len();
+        match self.watch {
+            Some(Watch::DepthChanges) if stack_depth != previous_stack_depth => Some(format!(
+                "stack depth changed from {} to {}",
+                previous_stack_depth, stack_depth
+            )),
+            Some(Watch::Depth(depth)) if stack_depth == depth && previous_stack_depth != depth => {
+                Some(format!("stack depth became {}", depth))
+            }
+            _ => None,
+        }
+    }
+
+    fn print_info(&self) {
+        if self.breakpoints.is_empty() {
+            eprintln!("no breakpoints");
+        }
+        for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+            eprintln!("breakpoint {}: {}", index, breakpoint);
+        }
+        if let Some(watch) = &self.watch {
+            eprintln!("watching: {}", watch);
+        }
+    }
+}
+
+fn print_state<N: Number>(interpreter: &Interpreter<N>) {
+    let position = interpreter.current_position();
+    let block = match interpreter.block_index_at(position) {
+        Some(index) => format!("#{} {}", index, interpreter.blocks()[index]),
+        None => String::from("none"),
+    };
+    let next_command = match interpreter.next_command() {
+        Some(command) => command.to_string(),
+        None => String::from("none"),
+    };
+    eprintln!(
+        "step: {}, dp: {}, cc: {}, pos: {:?}, block: {}, next command: {}",
+        interpreter.step_counter(),
+        interpreter.dp(),
+        interpreter.cc(),
+        position,
+        block,
+        next_command
+    );
+    let stack = interpreter
+        .stack()
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>();
+    eprintln!("stack: [{}]", stack.join(", "));
+}
+
+// parses a line typed at the prompt, `Ok(None)` for a blank line and a usage hint for mistakes
+fn parse_action(line: &str) -> Result<Option<Action>, String> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some(command) => command,
+        None => return Ok(None),
+    };
+    let argument = words.collect::<Vec<_>>().join(" ");
+    let action = match command {
+        "step" | "s" if argument.is_empty() => Action::Step(1),
+        "step" | "s" | "next" | "n" => match argument.parse::<u128>() {
+            Ok(steps) => Action::Step(steps),
+            Err(_) => return Err(format!("usage: {} <N>", command)),
+        },
+        "continue" | "c" => Action::Continue,
+        "break" | "b" => match parse_breakpoint(&argument) {
+            Some(breakpoint) => Action::Break(breakpoint),
+            None => {
+                return Err(String::from(
+                    "usage: break <x>,<y> | break block <N> | break <command>",
+                ))
+            }
+        },
+        "watch" | "w" if argument.is_empty() => Action::Watch(Watch::DepthChanges),
+        "watch" | "w" => match argument.parse::<usize>() {
+            Ok(depth) => Action::Watch(Watch::Depth(depth)),
+            Err(_) => return Err(String::from("usage: watch [<N>]")),
+        },
+        "unwatch" => Action::Unwatch,
+        "delete" | "d" => match argument.parse::<usize>() {
+            Ok(index) => Action::Delete(index),
+            Err(_) => {
+                return Err(String::from(
+                    "usage: delete <N>, see `info` for existing breakpoints",
+                ))
+            }
+        },
+        "info" | "i" => Action::Info,
+        "print" | "p" => Action::Print,
+        "help" | "h" => Action::Help,
+        "quit" | "q" => Action::Quit,
+        _ => {
+            return Err(format!(
+                "unknown command `{}`, type `help` for a list of commands",
+                command
+            ))
+        }
+    };
+    Ok(Some(action))
+}
+
+fn parse_breakpoint(argument: &str) -> Option<Breakpoint> {
+    if let Some(index) = argument.strip_prefix("block") {
+        return index.trim().parse::<usize>().ok().map(Breakpoint::Block);
+    }
+    if let Some((x, y)) = argument.split_once(',') {
+        let x = x.trim().parse::<usize>().ok()?;
+        let y = y.trim().parse::<usize>().ok()?;
+        return Some(Breakpoint::Codel(x, y));
+    }
+    (0..3)
+        .flat_map(|light| (0..6).map(move |hue| (light, hue)))
+        .filter_map(Command::from)
+        .find(|command| command.to_string().eq_ignore_ascii_case(argument))
+        .map(Breakpoint::Command)
+}
+
+/*
+ * SharedInput hands out readers of one buffered input, so the debugger's
+ * commands and the program's IN_CHAR and IN_NUMBER take turns on stdin. With
+ * separate buffers the first read of either would pull in input meant for
+ * the other.
+ */
+pub struct SharedInput<R> {
+    inner: Rc<RefCell<R>>,
+    // a copy of the start of the shared buffer, as `fill_buf` cannot lend the RefCell's content
+    peeked: Vec<u8>,
+}
+
+// only this much of the shared buffer is copied per `fill_buf`, as IN_CHAR and IN_NUMBER peek byte by byte
+const PEEK_LENGTH: usize = 64;
+
+impl<R: BufRead> SharedInput<R> {
+    pub fn new(inner: R) -> SharedInput<R> {
+        SharedInput {
+            inner: Rc::new(RefCell::new(inner)),
+            peeked: Vec::new(),
+        }
+    }
+}
+
+impl<R> Clone for SharedInput<R> {
+    fn clone(&self) -> SharedInput<R> {
+        SharedInput {
+            inner: Rc::clone(&self.inner),
+            peeked: Vec::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Read for SharedInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = {
+            let available = self.fill_buf()?;
+            let length = cmp::min(available.len(), buf.len());
+            buf[..length].copy_from_slice(&available[..length]);
+            length
+        };
+        self.consume(length);
+        Ok(length)
+    }
+}
+
+impl<R: BufRead> BufRead for SharedInput<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // another reader may have consumed from the shared buffer since the last call
+        let mut inner = self.inner.borrow_mut();
+        let available = inner.fill_buf()?;
+        self.peeked.clear();
+        self.peeked
+            .extend_from_slice(&available[..cmp::min(available.len(), PEEK_LENGTH)]);
+        Ok(&self.peeked)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.inner.borrow_mut().consume(amount);
+        self.peeked.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufRead};
+
+    use rpiet::{canvas_from_grid, Command, Config, Interpreter};
+
+    use super::{parse_action, Action, Breakpoint, Debugger, SharedInput, Watch};
+
+    #[test]
+    fn parses_steps() {
+        assert_eq!(parse_action("step"), Ok(Some(Action::Step(1))));
+        assert_eq!(parse_action("s 5"), Ok(Some(Action::Step(5))));
+        assert_eq!(parse_action("  next   12\n"), Ok(Some(Action::Step(12))));
+        assert_eq!(parse_action("n"), Err(String::from("usage: n <N>")));
+        assert_eq!(
+            parse_action("step -1"),
+            Err(String::from("usage: step <N>"))
+        );
+        assert_eq!(parse_action("continue"), Ok(Some(Action::Continue)));
+        assert_eq!(parse_action("c"), Ok(Some(Action::Continue)));
+    }
+
+    #[test]
+    fn parses_breakpoints() {
+        assert_eq!(
+            parse_action("break 3,4"),
+            Ok(Some(Action::Break(Breakpoint::Codel(3, 4))))
+        );
+        assert_eq!(
+            parse_action("b 3, 4"),
+            Ok(Some(Action::Break(Breakpoint::Codel(3, 4))))
+        );
+        assert_eq!(
+            parse_action("break block 12"),
+            Ok(Some(Action::Break(Breakpoint::Block(12))))
+        );
+        assert_eq!(
+            parse_action("break roll"),
+            Ok(Some(Action::Break(Breakpoint::Command(Command::Roll))))
+        );
+        assert_eq!(
+            parse_action("break IN_CHAR"),
+            Ok(Some(Action::Break(Breakpoint::Command(Command::InChar))))
+        );
+        for argument in ["", "3", "3,x", "block", "frobnicate"].iter() {
+            assert!(
+                parse_action(&format!("break {}", argument)).is_err(),
+                "{}",
+                argument
+            );
+        }
+    }
+
+    #[test]
+    fn parses_watches_and_other_commands() {
+        assert_eq!(
+            parse_action("watch"),
+            Ok(Some(Action::Watch(Watch::DepthChanges)))
+        );
+        assert_eq!(
+            parse_action("w 3"),
+            Ok(Some(Action::Watch(Watch::Depth(3))))
+        );
+        assert_eq!(
+            parse_action("watch deep"),
+            Err(String::from("usage: watch [<N>]"))
+        );
+        assert_eq!(parse_action("unwatch"), Ok(Some(Action::Unwatch)));
+        assert_eq!(parse_action("delete 1"), Ok(Some(Action::Delete(1))));
+        assert_eq!(parse_action("q"), Ok(Some(Action::Quit)));
+        assert_eq!(parse_action("  \n"), Ok(None));
+        assert!(parse_action("jump 3").is_err());
+    }
+
+    #[test]
+    fn codel_breakpoints_trigger_on_any_codel_of_a_block() {
+        // the dark red block is entered at (2, 0)
+        let canvas = canvas_from_grid("R  R  dR dR W  W\nK  K  K  K  W  W").unwrap();
+        let mut interpreter =
+            Interpreter::from_rgb_rows_with_io(canvas, &Config::default(), io::empty(), io::sink())
+                .unwrap();
+        let mut debugger = Debugger::new();
+        debugger.breakpoints.push(Breakpoint::Codel(3, 0));
+        interpreter.advance();
+        assert_eq!(interpreter.current_position(), (2, 0));
+        assert_eq!(
+            debugger.stop_reason(&interpreter, (1, 0), 0),
+            Some(String::from("breakpoint 0 (codel (3, 0))"))
+        );
+        // toggling pointers inside the block does not trigger it again
+        assert_eq!(debugger.stop_reason(&interpreter, (2, 0), 1), None);
+    }
+
+    #[test]
+    fn command_breakpoints_trigger_before_the_first_step() {
+        let canvas = canvas_from_grid("R  R  dR\nK  K  K").unwrap();
+        let mut interpreter =
+            Interpreter::from_rgb_rows_with_io(canvas, &Config::default(), io::empty(), io::sink())
+                .unwrap();
+        let mut debugger = Debugger::new();
+        debugger
+            .breakpoints
+            .push(Breakpoint::Command(Command::Push));
+        debugger.advance(&mut interpreter, u128::MAX);
+        assert_eq!(interpreter.step_counter(), 0);
+        // stepping from the breakpoint executes the command
+        debugger.advance(&mut interpreter, 1);
+        assert_eq!(interpreter.stack(), [2]);
+        // POP leads back into the red block, which pushes again
+        debugger.advance(&mut interpreter, u128::MAX);
+        assert_eq!(interpreter.step_counter(), 10);
+        assert_eq!(interpreter.stack(), []);
+    }
+
+    #[test]
+    fn shared_input_readers_take_turns() {
+        let input = SharedInput::new(&b"step\n42 rest\ncontinue\n"[..]);
+        let (mut commands, mut program) = (input.clone(), input);
+        let mut line = String::new();
+        commands.read_line(&mut line).unwrap();
+        assert_eq!(line, "step\n");
+        // the program reads only what it needs
+        let mut number = [0; 2];
+        io::Read::read_exact(&mut program, &mut number).unwrap();
+        assert_eq!(&number, b"42");
+        line.clear();
+        commands.read_line(&mut line).unwrap();
+        assert_eq!(line, " rest\n");
+        line.clear();
+        commands.read_line(&mut line).unwrap();
+        assert_eq!(line, "continue\n");
+        assert_eq!(program.fill_buf().unwrap(), b"");
+    }
+}
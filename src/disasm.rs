@@ -0,0 +1,156 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::command::Command;
+use crate::interpreter::Interpreter;
+use crate::number::Number;
+use crate::state_graph::{Location, State, StateGraph};
+use crate::transition::POINTER_STATES;
+
+/// Lists every state the interpreter can reach from its current one, as straight-line
+/// runs of transitions between labeled branch points.
+///
+/// A state is a color block (or the white codel the program starts on) together with DP
+/// and CC. Every line shows one step, e.g.
+/// `block#12 (dark red, 5 codels) --dp=right,cc=left--> block#13: PUSH 5`, the color and
+/// size of the next block are shown on its own steps.
+/// Runs end in a jump to another run, a branch after POINTER or SWITCH (which may
+/// continue in each direction), a block which cannot be left or a slide which gets
+/// trapped in white, both of which end the program.
+pub fn disassemble<N: Number>(interpreter: &Interpreter<N>, source_name: &str) -> String {
+    let graph = StateGraph::new(interpreter);
+    let start = graph.start_state(interpreter);
+    let successors = graph.reachable_states(start);
+    let mut predecessor_counts = HashMap::new();
+    let mut branch_targets = HashSet::new();
+    for next_states in successors.values() {
+        for &next_state in next_states.iter() {
+            *predecessor_counts.entry(next_state).or_insert(0) += 1;
+            if next_states.len() > 1 {
+                branch_targets.insert(next_state);
+            }
+        }
+    }
+    let starts_run = |state: State| {
+        state == start
+            || predecessor_counts.get(&state) != Some(&1)
+            || branch_targets.contains(&state)
+    };
+
+    // runs in the order they are reached, following the first branch first
+    let mut runs: Vec<Vec<State>> = Vec::new();
+    let mut listed = HashSet::new();
+    let mut pending = vec![start];
+    while let Some(first_state) = pending.pop() {
+        if !listed.insert(first_state) {
+            continue;
+        }
+        let mut run = vec![first_state];
+        loop {
+            let next_states = &successors[run.last().unwrap()];
+            match next_states[..] {
+                [next_state] if !starts_run(next_state) => {
+                    listed.insert(next_state);
+                    run.push(next_state);
+                }
+                _ => {
+                    pending.extend(next_states.iter().rev());
+                    break;
+                }
+            }
+        }
+        runs.push(run);
+    }
+    let labels = runs
+        .iter()
+        .enumerate()
+        .map(|(index, run)| (run[0], index))
+        .collect::<HashMap<_, _>>();
+
+    let mut listing = String::new();
+    let _ = writeln!(
+        listing,
+        "# {}: {} blocks, {} reachable states, starting at L0",
+        source_name,
+        interpreter.blocks().len(),
+        successors.len()
+    );
+    for (index, run) in runs.iter().enumerate() {
+        let _ = writeln!(listing, "\nL{}:", index);
+        for &state in run.iter() {
+            let _ = writeln!(listing, "    {}", describe_step(interpreter, &graph, state));
+        }
+        let next_states = &successors[run.last().unwrap()];
+        match next_states[..] {
+            [] => (),
+            [next_state] => {
+                let _ = writeln!(listing, "    -> L{}", labels[&next_state]);
+            }
+            _ => {
+                let branches = next_states
+                    .iter()
+                    .map(|next_state| {
+                        let (dp, cc) = POINTER_STATES[next_state.pointer];
+                        format!("dp={},cc={} -> L{}", dp, cc, labels[next_state])
+                    })
+                    .collect::<Vec<_>>();
+                let _ = writeln!(listing, "    branch: {}", branches.join(", "));
+            }
+        }
+    }
+    listing
+}
+
+fn describe_step<N: Number>(
+    interpreter: &Interpreter<N>,
+    graph: &StateGraph,
+    state: State,
+) -> String {
+    let location = graph.locations[state.location];
+    let from = location.describe(interpreter);
+    let (dp, cc) = POINTER_STATES[state.pointer];
+    if graph.is_terminal(state.location) {
+        return format!(
+            "{} dp={},cc={}: cannot move in any direction, the program ends",
+            from, dp, cc
+        );
+    }
+    let toggle = if state.toggle_dp_next {
+        "rotate DP"
+    } else {
+        "toggle CC"
+    };
+    let edge = match graph.edges[state.location][state.pointer] {
+        Some(edge) => edge,
+        None => return format!("{} --dp={},cc={}--> blocked, {}", from, dp, cc, toggle),
+    };
+    let action = match (
+        edge.transition.reached_new_block,
+        edge.transition.command,
+        location,
+    ) {
+        (false, _, _) if graph.compat.slides_turn_in_white() => {
+            String::from("trapped in white, the program ends")
+        }
+        (false, _, _) => format!("stuck in white, {}", toggle),
+        (true, Some(Command::Push), Location::Block(block_index)) => {
+            format!("PUSH {}", interpreter.blocks()[block_index].size())
+        }
+        (true, Some(command), _) => command.to_string(),
+        (true, None, _) if (edge.transition.dp, edge.transition.cc) == (dp, cc) => {
+            String::from("slide through white")
+        }
+        (true, None, _) => format!(
+            "slide through white, turning to dp={},cc={}",
+            edge.transition.dp, edge.transition.cc
+        ),
+    };
+    format!(
+        "{} --dp={},cc={}--> {}: {}",
+        from,
+        dp,
+        cc,
+        graph.locations[edge.target].name(),
+        action
+    )
+}
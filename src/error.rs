@@ -0,0 +1,124 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/*
+ * RpietError lists everything that can go wrong while loading an image and
+ * setting up an interpreter for it (including colors outside of the Piet palette
+ * under `UnknownColor::Error`), the arithmetic overflow which halts a running
+ * program under `ArithmeticPolicy::Halt`, and mistakes in assembler sources and
+ * in the ASCII grids of `canvas_from_grid`.
+ */
+#[derive(Debug)]
+pub enum RpietError {
+    Io(io::Error),
+    Decode(String),
+    UnsupportedFormat(String),
+    UnsupportedColorType(String),
+    CodelSizeMismatch {
+        codel_size: u32,
+        width: u32,
+        height: u32,
+    },
+    EmptyImage,
+    UnknownColor {
+        color: (u8, u8, u8),
+        position: (usize, usize),
+    },
+    InvalidPalette {
+        line: usize,
+        message: String,
+    },
+    ArithmeticOverflow(String),
+    InvalidAssembly {
+        line: usize,
+        message: String,
+    },
+    InvalidGrid {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for RpietError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RpietError::Io(e) => write!(f, "{}", e),
+            RpietError::Decode(message) => write!(f, "could not decode image: {}", message),
+            RpietError::UnsupportedFormat(file_path) => write!(
+                f,
+                "unknown file type of {}, the file format must be one of {{png, gif}}",
+                file_path
+            ),
+            RpietError::UnsupportedColorType(color_type) => {
+                write!(f, "unsupported color type {}", color_type)
+            }
+            RpietError::CodelSizeMismatch {
+                codel_size,
+                width,
+                height,
+            } => write!(
+                f,
+                "codel_size {} does not fit into image dimensions ({}, {})",
+                codel_size, width, height
+            ),
+            RpietError::EmptyImage => write!(f, "the image does not contain any codels"),
+            RpietError::UnknownColor {
+                color: (r, g, b),
+                position: (x, y),
+            } => write!(
+                f,
+                "unknown codel color #{:02X}{:02X}{:02X} at position ({}, {})",
+                r, g, b, x, y
+            ),
+            RpietError::InvalidPalette { line: 0, message } => {
+                write!(f, "invalid palette: {}", message)
+            }
+            RpietError::InvalidPalette { line, message } => {
+                write!(f, "invalid palette in line {}: {}", line, message)
+            }
+            RpietError::ArithmeticOverflow(operation) => {
+                write!(f, "arithmetic overflow in {}", operation)
+            }
+            RpietError::InvalidAssembly { line, message } => {
+                write!(f, "invalid assembly in line {}: {}", line, message)
+            }
+            RpietError::InvalidGrid { line, message } => {
+                write!(f, "invalid grid in line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl Error for RpietError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RpietError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RpietError {
+    fn from(e: io::Error) -> RpietError {
+        RpietError::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for RpietError {
+    fn from(e: png::DecodingError) -> RpietError {
+        match e {
+            png::DecodingError::IoError(e) => RpietError::Io(e),
+            e => RpietError::Decode(e.to_string()),
+        }
+    }
+}
+
+impl From<gif::DecodingError> for RpietError {
+    fn from(e: gif::DecodingError) -> RpietError {
+        match e {
+            gif::DecodingError::Io(e) => RpietError::Io(e),
+            e => RpietError::Decode(e.to_string()),
+        }
+    }
+}
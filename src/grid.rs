@@ -0,0 +1,76 @@
+use crate::error::RpietError;
+use crate::palette::{Palette, PaletteColor};
+use crate::utils::RgbRows;
+
+const HUE_LETTERS: [char; 6] = ['R', 'Y', 'G', 'C', 'B', 'M'];
+
+/// Builds a canvas of one codel per color name from a grid written in ASCII, e.g. to
+/// write interpreter edge cases inline in tests.
+///
+/// Each line is one row of codels with color names separated by whitespace:
+///
+/// ```text
+/// lR R  dR W  W
+/// K  K  K  .  .
+/// ```
+///
+/// `R`, `Y`, `G`, `C`, `B` and `M` are red, yellow, green, cyan, blue and magenta,
+/// an `l` or `d` prefix makes them light or dark. `W` and `.` are white, `K` is black.
+/// Blank lines are skipped, so the grid may start on the line after the opening quote.
+/// The canvas can be run with [`Interpreter::from_rgb_rows`](crate::Interpreter::from_rgb_rows)
+/// or saved with [`write_png`](crate::write_png).
+pub fn canvas_from_grid(grid: &str) -> Result<RgbRows, RpietError> {
+    let palette = Palette::standard();
+    let mut canvas: RgbRows = Vec::new();
+    for (index, line) in grid.lines().enumerate() {
+        let error = |message: String| RpietError::InvalidGrid {
+            line: index + 1,
+            message,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row = line
+            .split_whitespace()
+            .map(|name| {
+                parse_color(name)
+                    .and_then(|color| palette.rgb(color))
+                    .ok_or_else(|| error(format!("unknown color `{}`", name)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(first_row) = canvas.first() {
+            if row.len() != first_row.len() {
+                return Err(error(format!(
+                    "the row has {} codels, but the first row has {}",
+                    row.len(),
+                    first_row.len()
+                )));
+            }
+        }
+        canvas.push(row);
+    }
+    if canvas.is_empty() {
+        return Err(RpietError::EmptyImage);
+    }
+    Ok(canvas)
+}
+
+fn parse_color(name: &str) -> Option<PaletteColor> {
+    match name {
+        "W" | "." => return Some(PaletteColor::White),
+        "K" => return Some(PaletteColor::Black),
+        _ => (),
+    }
+    let mut chars = name.chars();
+    let (light, letter) = match (chars.next()?, chars.next(), chars.next()) {
+        (letter, None, None) => (1, letter),
+        ('l', Some(letter), None) => (0, letter),
+        ('d', Some(letter), None) => (2, letter),
+        _ => return None,
+    };
+    let hue = HUE_LETTERS.iter().position(|&hue| hue == letter)?;
+    Some(PaletteColor::Color {
+        hue: hue as u8,
+        light,
+    })
+}
@@ -1,19 +1,26 @@
+use std::collections::HashSet;
 use std::fmt;
+use std::io::{self, BufRead, Write};
 
 use crate::block::Block;
 use crate::block_exit::BlockExit;
-use crate::cmd_options::CmdOptions;
 use crate::command::Command;
+use crate::config::{Compat, Config, UnknownColor};
 use crate::counters::{CodelChooser, Counters, DirectionPointer};
+use crate::error::RpietError;
+use crate::number::Number;
+use crate::palette::PaletteColor;
+use crate::streams::Streams;
+use crate::trace::{Event, Step, Tracer};
+use crate::transition::{Transition, TransitionTable};
 
 // TODO: this file is too big, needs being split up
-// TODO: we needs tests (also for other modules)
 // TODO: needs better module/method level documentation
-// TODO: parse gif files using https://github.com/image-rs/image-gif
 
 const MAX_ALLOWED__POINTER_TOGGLES: u8 = 8;
 const LIGHT_LEVELS: u8 = 3;
 const HUE_LEVELS: u8 = 6;
+const MAX_LISTED_UNKNOWN_COLORS: usize = 10;
 
 #[derive(Debug)]
 enum Codel {
@@ -54,28 +61,72 @@ impl fmt::Display for Codel {
     }
 }
 
-#[derive(Debug)]
-pub struct Interpreter {
+pub struct Interpreter<'a, N: Number = i64> {
     dp: DirectionPointer,
     cc: CodelChooser,
     alive: bool,
-    stack: Vec<i64>,
+    stack: Vec<N>,
     step_counter: u128,
-    max_steps: u128,
-    unlimited_steps: bool,
-    verbose_logging: bool,
+    config: Config,
+    error: Option<RpietError>,
     canvas: Vec<Vec<Codel>>,
     blocks: Vec<Block>,
     width: usize,
     height: usize,
     current_position: (usize, usize),
     toggled_pointers_without_move: u8,
+    transitions: Option<TransitionTable>,
     last_toggled_pointer: Counters,
+    streams: Streams<'a>,
+    tracers: Vec<Box<dyn Tracer<N> + 'a>>,
+}
+
+impl Interpreter<'static> {
+    /// Creates an interpreter which reads from stdin and writes to stdout.
+    pub fn from_rgb_rows(
+        rgb_rows: Vec<Vec<(u8, u8, u8)>>,
+        config: &Config,
+    ) -> Result<Interpreter<'static>, RpietError> {
+        Interpreter::with_streams(rgb_rows, config, Streams::stdio())
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    /// Creates an interpreter which reads program input from `input` and writes program output to `output`.
+    ///
+    /// This allows running a Piet program against in-memory buffers, e.g. to capture its output.
+    pub fn from_rgb_rows_with_io(
+        rgb_rows: Vec<Vec<(u8, u8, u8)>>,
+        config: &Config,
+        input: impl BufRead + 'a,
+        output: impl Write + 'a,
+    ) -> Result<Interpreter<'a>, RpietError> {
+        Interpreter::with_streams(rgb_rows, config, Streams::new(input, output))
+    }
 }
 
-impl Interpreter {
-    pub fn from_rgb_rows(rgb_rows: Vec<Vec<(u8, u8, u8)>>, options: &CmdOptions) -> Interpreter {
-        let canvas = create_canvas(rgb_rows, options);
+impl<'a, N: Number> Interpreter<'a, N> {
+    /// Creates an interpreter whose stack holds numbers of type `N`, e.g. `Interpreter::<BigInt>::new(..)`.
+    ///
+    /// `from_rgb_rows` and `from_rgb_rows_with_io` are shortcuts for the default `i64` stack.
+    pub fn new(
+        rgb_rows: Vec<Vec<(u8, u8, u8)>>,
+        config: &Config,
+        input: impl BufRead + 'a,
+        output: impl Write + 'a,
+    ) -> Result<Interpreter<'a, N>, RpietError> {
+        Interpreter::with_streams(rgb_rows, config, Streams::new(input, output))
+    }
+
+    fn with_streams(
+        rgb_rows: Vec<Vec<(u8, u8, u8)>>,
+        config: &Config,
+        streams: Streams<'a>,
+    ) -> Result<Interpreter<'a, N>, RpietError> {
+        if rgb_rows.is_empty() || rgb_rows[0].is_empty() {
+            return Err(RpietError::EmptyImage);
+        }
+        let canvas = create_canvas(rgb_rows, config)?;
         let width = canvas[0].len();
         let height = canvas.len();
         let mut interpreter = Interpreter {
@@ -84,71 +135,190 @@ impl Interpreter {
             alive: true,
             stack: Vec::with_capacity(64),
             step_counter: 0,
-            max_steps: options.max_steps,
-            unlimited_steps: options.unlimited_steps,
-            verbose_logging: options.verbose,
+            config: config.clone(),
+            error: None,
             canvas,
             blocks: Vec::new(),
             width,
             height,
             current_position: (0, 0),
             toggled_pointers_without_move: 0,
+            transitions: None,
             last_toggled_pointer: Counters::DirectionPointer,
+            streams,
+            tracers: Vec::new(),
         };
         interpreter.detect_blocks();
-        interpreter.assign_codels_to_blocks();
         interpreter.find_exits_for_blocks();
-        interpreter
+        if config.precompute_transitions {
+            interpreter.precompute_transitions();
+        }
+        Ok(interpreter)
     }
 
     pub fn is_alive(&self) -> bool {
         self.alive
     }
 
+    pub fn dp(&self) -> DirectionPointer {
+        self.dp
+    }
+
+    pub fn cc(&self) -> CodelChooser {
+        self.cc
+    }
+
+    pub fn current_position(&self) -> (usize, usize) {
+        self.current_position
+    }
+
+    pub fn stack(&self) -> &[N] {
+        &self.stack
+    }
+
+    pub fn step_counter(&self) -> u128 {
+        self.step_counter
+    }
+
+    /// Returns the error which halted the program, e.g. an overflow under `ArithmeticPolicy::Halt`.
+    pub fn error(&self) -> Option<&RpietError> {
+        self.error.as_ref()
+    }
+
+    /// Like `error`, but hands the error over to the caller.
+    pub fn take_error(&mut self) -> Option<RpietError> {
+        self.error.take()
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub(crate) fn compat(&self) -> Compat {
+        self.config.compat
+    }
+
+    /// Returns the index (into `blocks()`) of the color block containing the codel at `coord`.
+    pub fn block_index_at(&self, coord: (usize, usize)) -> Option<usize> {
+        if coord.0 >= self.width || coord.1 >= self.height {
+            return None;
+        }
+        match self.codel_for(coord) {
+            Codel::Color { block_index, .. } => *block_index,
+            _ => None,
+        }
+    }
+
+    /// Returns the command the next call to `advance` will execute, if any.
+    pub fn next_command(&self) -> Option<Command> {
+        self.next_transition()
+            .and_then(|transition| transition.command)
+    }
+
+    /// Registers a tracer which gets notified about every following step.
+    pub fn add_tracer(&mut self, tracer: Box<dyn Tracer<N> + 'a>) {
+        self.tracers.push(tracer);
+    }
+
+    /// Finishes all registered tracers, returning the first error any of them ran into.
+    pub fn finish_tracing(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for tracer in self.tracers.iter_mut() {
+            let finished = tracer.finish();
+            if result.is_ok() {
+                result = finished;
+            }
+        }
+        result
+    }
+
     pub fn advance(&mut self) {
         self.step_counter += 1;
-        if self.max_steps_reached() || self.program_should_end() {
+        let old_position = self.current_position;
+        let block_size = self.current_block().map(Block::size);
+        let event = if self.max_steps_reached() || self.program_should_end() {
             self.exit();
-            return;
-        }
-        match self.find_next_codel() {
-            Some((new_position, traveled_through_white, reached_new_block)) => {
-                self.toggled_pointers_without_move = 0;
-                let old_position = self.current_position;
-                self.current_position = new_position;
-                if reached_new_block {
-                    if !traveled_through_white {
-                        let cmd = self.command_to_execute(old_position, new_position);
-                        self.execute(cmd.unwrap(), old_position);
+            Event::Exit
+        } else {
+            match self.next_transition() {
+                Some(transition) => {
+                    self.toggled_pointers_without_move = 0;
+                    if self.config.compat.restarts_toggling_after_move() {
+                        // the next blocked move starts over with toggling CC
+                        self.last_toggled_pointer = Counters::DirectionPointer;
                     }
-                } else {
+                    self.current_position = transition.new_position;
+                    self.dp = transition.dp;
+                    self.cc = transition.cc;
+                    if !transition.reached_new_block && self.config.compat.slides_turn_in_white() {
+                        // trapped in a white area
+                        self.exit();
+                        Event::Exit
+                    } else if !transition.reached_new_block {
+                        // stopped on a white codel, see `Compat::Rpiet`
+                        self.toogle_counters();
+                        Event::Toggle
+                    } else if transition.traveled_through_white {
+                        Event::Slide
+                    } else {
+                        let cmd = transition.command.unwrap();
+                        self.execute(cmd, old_position);
+                        Event::Command(cmd)
+                    }
+                }
+                None => {
                     self.toogle_counters();
+                    self.toggled_pointers_without_move += 1;
+                    Event::Toggle
                 }
             }
-            None => {
-                self.toogle_counters();
-                self.toggled_pointers_without_move += 1;
-            }
         };
+        self.trace(old_position, block_size, event);
+    }
+
+    fn trace(&mut self, old_position: (usize, usize), block_size: Option<usize>, event: Event) {
+        if self.tracers.is_empty() {
+            return;
+        }
+        let step = Step {
+            step: self.step_counter,
+            old_position,
+            new_position: self.current_position,
+            dp: self.dp,
+            cc: self.cc,
+            event,
+            block_size,
+            stack: &self.stack,
+        };
+        for tracer in self.tracers.iter_mut() {
+            tracer.trace(&step);
+        }
     }
 
     fn execute(&mut self, command: Command, old_position: (usize, usize)) {
-        if let Codel::Color { block_index, .. } = self.canvas[old_position.1][old_position.0] {
-            if let Some(block_index) = block_index {
-                let block_size = self.blocks[block_index].size();
-                command.execute(
-                    &mut self.stack,
-                    &mut self.dp,
-                    &mut self.cc,
-                    block_size,
-                    self.verbose_logging,
-                );
+        if let Codel::Color {
+            block_index: Some(block_index),
+            ..
+        } = self.canvas[old_position.1][old_position.0]
+        {
+            let block_size = self.blocks[block_index].size();
+            let result = command.execute(
+                &mut self.stack,
+                &mut self.dp,
+                &mut self.cc,
+                block_size,
+                &mut self.streams,
+                &self.config,
+            );
+            if let Err(error) = result {
+                self.error = Some(error);
+                self.exit();
             }
         }
     }
 
     fn command_to_execute(
-        &mut self,
+        &self,
         old_position: (usize, usize),
         new_position: (usize, usize),
     ) -> Option<Command> {
@@ -168,10 +338,12 @@ impl Interpreter {
 
     fn block_for_coord(&self, coord: (usize, usize)) -> Option<&Block> {
         let codel = self.codel_for(coord);
-        if let Codel::Color { block_index, .. } = codel {
-            if let Some(block_index) = block_index {
-                return Some(&self.blocks[*block_index]);
-            }
+        if let Codel::Color {
+            block_index: Some(block_index),
+            ..
+        } = codel
+        {
+            return Some(&self.blocks[*block_index]);
         }
         None
     }
@@ -180,77 +352,112 @@ impl Interpreter {
         self.block_for_coord(self.current_position)
     }
 
-    fn find_next_codel(&self) -> Option<((usize, usize), bool, bool)> {
-        let origin_block = self.current_block();
-        let mut coord = match origin_block {
-            Some(block) => block.exit_coordinates(&self.dp, &self.cc)?,
-            None => (self.current_position.0, self.current_position.1),
+    // where the interpreter goes next, a table lookup when the transitions were precomputed
+    fn next_transition(&self) -> Option<Transition> {
+        match (
+            &self.transitions,
+            self.block_index_at(self.current_position),
+        ) {
+            (Some(transitions), Some(block_index)) => {
+                transitions.get(block_index, self.dp, self.cc)
+            }
+            _ => self.find_transition(self.current_position, self.dp, self.cc),
+        }
+    }
+
+    pub(crate) fn find_transition(
+        &self,
+        position: (usize, usize),
+        dp: DirectionPointer,
+        cc: CodelChooser,
+    ) -> Option<Transition> {
+        let origin_block_index = match self.block_index_at(position) {
+            Some(block_index) => block_index,
+            // the start position, or a white codel a slide stopped at with `Compat::Rpiet`
+            None => return self.slide_through_white(position, dp, cc),
         };
+        let exit = self.blocks[origin_block_index].exit_coordinates(&dp, &cc)?;
+        match *self.find_next_codel_from(exit, dp)? {
+            Codel::Black { .. } => None,
+            Codel::White { .. } => self.slide_through_white(exit, dp, cc),
+            Codel::Color { x, y, .. } => Some(Transition {
+                new_position: (x, y),
+                dp,
+                cc,
+                traveled_through_white: false,
+                reached_new_block: true,
+                command: self.command_to_execute(position, (x, y)),
+            }),
+        }
+    }
 
-        let mut traveled_through_white = false;
-        while let Some(next_codel) = self.find_next_codel_from(coord) {
-            match *next_codel {
-                Codel::Black { .. } => break,
-                Codel::White { x, y } => {
-                    traveled_through_white = true;
-                    coord = (x, y);
+    // Slides in DP direction from `start` over white codels until a color block is reached.
+    // Every black codel or edge in the way toggles CC and rotates DP clockwise before the
+    // slide continues. Coming back to a codel with a DP it already had at such a turn means
+    // the slide would go around in circles forever, this traps the interpreter.
+    // With `Compat::Rpiet` the slide stops at the first obstacle instead, or does not
+    // move at all when `start` is a white codel in front of one.
+    fn slide_through_white(
+        &self,
+        start: (usize, usize),
+        mut dp: DirectionPointer,
+        mut cc: CodelChooser,
+    ) -> Option<Transition> {
+        let mut coord = start;
+        let mut turns = HashSet::new();
+        loop {
+            match self.find_next_codel_from(coord, dp) {
+                Some(&Codel::White { x, y }) => coord = (x, y),
+                Some(&Codel::Color { x, y, .. }) => {
+                    return Some(Transition {
+                        new_position: (x, y),
+                        dp,
+                        cc,
+                        traveled_through_white: true,
+                        reached_new_block: true,
+                        command: None,
+                    })
                 }
-                Codel::Color {
-                    x, y, block_index, ..
-                } => {
-                    let block_index = block_index?;
-                    match origin_block {
-                        Some(origin_block) => {
-                            if origin_block != &self.blocks[block_index] {
-                                return Some(((x, y), traveled_through_white, true));
-                            }
-                        }
-                        None => {
-                            // must travel through white when not coming from a color with a block (= color codel)
-                            // this can happen e.g. when arriving ad black/edge while sliding through a white area
-                            return Some(((x, y), true, true));
-                        }
-                    }
-                    coord = (x, y);
-                }
-            }
-        }
-        // reached an end of our current travel in the given direction (black codel or picture edge)
-        match origin_block {
-            Some(_) => {
-                match self.block_for_coord(coord) {
-                    Some(_) => {
-                        // we ended in a block, but it is the same block as before (the case of a different block is handled
-                        // with an early return above). This means we could not *actually* move out of the block and cannot report a new position
-                        None
+                Some(&Codel::Black { .. }) | None => {
+                    let stopped = !self.config.compat.slides_turn_in_white();
+                    if stopped && coord == start {
+                        return None;
                     }
-                    None => {
-                        // we started from a colored codel (-> with block) and arrived at a colored codel, so we moved but ended
-                        // in a position without executable command (since that case is handled with an early return above).
-                        // pointers (dp,cc) need to be toggled and jouney needs to continue from the current coords
-                        // must travel through white when arriving at a color with a block (= color codel)
-                        Some((coord, true, false))
+                    if stopped || !turns.insert((coord, dp)) {
+                        return Some(Transition {
+                            new_position: coord,
+                            dp,
+                            cc,
+                            traveled_through_white: true,
+                            reached_new_block: false,
+                            command: None,
+                        });
                     }
-                }
-            }
-            None => {
-                if self.current_position != coord {
-                    // we started from a not-colored codel (-> no block) and arrived at a non-colored codel, so we moved but ended
-                    // in a position without executable command (since that case is handled with an early return above).
-                    // pointers (dp,cc) need to be toggled and jouney needs to continue from the current coords
-                    // must travel through white when not coming from a color with a block (= color codel)
-                    // this can happen e.g. when arriving ad black/edge while sliding through a white area
-                    Some((coord, true, false))
-                } else {
-                    // we did not move at all, so we cannod return a new position
-                    None
+                    cc = match cc {
+                        CodelChooser::Right => CodelChooser::Left,
+                        CodelChooser::Left => CodelChooser::Right,
+                    };
+                    dp = match dp {
+                        DirectionPointer::Up => DirectionPointer::Right,
+                        DirectionPointer::Right => DirectionPointer::Down,
+                        DirectionPointer::Down => DirectionPointer::Left,
+                        DirectionPointer::Left => DirectionPointer::Up,
+                    };
                 }
             }
         }
     }
 
-    fn find_next_codel_from(&self, start: (usize, usize)) -> Option<&Codel> {
-        let next_coords = match self.dp {
+    fn precompute_transitions(&mut self) {
+        let transitions = TransitionTable::new(self.blocks.len(), |block_index, dp, cc| {
+            let position = self.blocks[block_index].codel_coordinates[0];
+            self.find_transition(position, dp, cc)
+        });
+        self.transitions = Some(transitions);
+    }
+
+    fn find_next_codel_from(&self, start: (usize, usize), dp: DirectionPointer) -> Option<&Codel> {
+        let next_coords = match dp {
             DirectionPointer::Up => coord_up(start, self.width, self.height),
             DirectionPointer::Right => coord_right(start, self.width, self.height),
             DirectionPointer::Down => coord_down(start, self.width, self.height),
@@ -264,7 +471,7 @@ impl Interpreter {
     }
 
     fn max_steps_reached(&self) -> bool {
-        !self.unlimited_steps && self.step_counter >= self.max_steps
+        !self.config.unlimited_steps && self.step_counter >= self.config.max_steps
     }
 
     fn program_should_end(&self) -> bool {
@@ -294,111 +501,65 @@ impl Interpreter {
 
     fn exit(&mut self) {
         self.alive = false;
+        let _ = self.streams.output.flush();
     }
 
-    fn assign_codels_to_blocks(&mut self) {
-        for row in self.canvas.iter_mut() {
-            for codel in row.iter_mut() {
-                if let Codel::Color {
-                    block_index, x, y, ..
-                } = codel
-                {
-                    *block_index = self
-                        .blocks
-                        .iter_mut()
-                        .position(|b| b.codel_coordinates.contains(&(*x, *y)));
-                }
-            }
-        }
-    }
-
+    // flood fills every color block once, labeling its codels with the block index on the way
     fn detect_blocks(&mut self) {
-        let mut visited: Vec<Vec<bool>> = vec![vec![false; self.width]; self.height];
-        for row in self.canvas.iter() {
-            for codel in row.iter() {
-                if let Codel::Color {
-                    x, y, hue, light, ..
-                } = codel
-                {
-                    if visited[*y][*x] {
-                        continue;
-                    }
-                    let block = Block {
-                        codel_coordinates: Vec::new(),
-                        hue: *hue,
-                        light: *light,
-                        block_exit: None,
-                    };
-                    self.blocks.push(block);
-                    let new_block_index = self.blocks.len() - 1;
-
-                    let mut visit_list: Vec<(usize, usize)> = [(*x, *y)].to_vec();
-                    while !visit_list.is_empty() {
-                        let coord = visit_list.pop().unwrap();
-                        if visited[coord.1][coord.0] {
-                            continue;
-                        }
-                        visited[coord.1][coord.0] = true;
-                        let block = &mut self.blocks[new_block_index];
-                        block.codel_coordinates.push(coord);
-
-                        // right neighbour
-                        if let Some(other_coord) = coord_right(coord, self.width, self.height) {
-                            let other_codel = &self.canvas[other_coord.1][other_coord.0];
-                            if let Codel::Color {
-                                x, y, hue, light, ..
-                            } = other_codel
-                            {
-                                if !visited[*y][*x] && block.hue == *hue && block.light == *light {
-                                    visit_list.push(other_coord);
-                                }
-                            }
-                        }
-
-                        // left neighbour
-                        if let Some(other_coord) = coord_left(coord, self.width, self.height) {
-                            let other_codel = &self.canvas[other_coord.1][other_coord.0];
-                            if let Codel::Color {
-                                x, y, hue, light, ..
-                            } = other_codel
-                            {
-                                if !visited[*y][*x] && block.hue == *hue && block.light == *light {
-                                    visit_list.push(other_coord);
-                                }
-                            }
-                        }
-
-                        // up neighbour
-                        if let Some(other_coord) = coord_up(coord, self.width, self.height) {
-                            let other_codel = &self.canvas[other_coord.1][other_coord.0];
-                            if let Codel::Color {
-                                x, y, hue, light, ..
-                            } = other_codel
-                            {
-                                if !visited[*y][*x] && block.hue == *hue && block.light == *light {
-                                    visit_list.push(other_coord);
-                                }
-                            }
-                        }
-
-                        // down neighbour
-                        if let Some(other_coord) = coord_down(coord, self.width, self.height) {
-                            let other_codel = &self.canvas[other_coord.1][other_coord.0];
-                            if let Codel::Color {
-                                x, y, hue, light, ..
-                            } = other_codel
-                            {
-                                if !visited[*y][*x] && block.hue == *hue && block.light == *light {
-                                    visit_list.push(other_coord);
-                                }
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (hue, light) = match self.canvas[y][x] {
+                    Codel::Color {
+                        hue,
+                        light,
+                        block_index: None,
+                        ..
+                    } => (hue, light),
+                    _ => continue,
+                };
+                let new_block_index = self.blocks.len();
+                let mut codel_coordinates = Vec::new();
+                self.set_block_index((x, y), new_block_index);
+                let mut visit_list: Vec<(usize, usize)> = vec![(x, y)];
+                while let Some(coord) = visit_list.pop() {
+                    codel_coordinates.push(coord);
+                    let neighbours = [
+                        coord_right(coord, self.width, self.height),
+                        coord_left(coord, self.width, self.height),
+                        coord_up(coord, self.width, self.height),
+                        coord_down(coord, self.width, self.height),
+                    ];
+                    for other_coord in neighbours.iter().flatten() {
+                        if let Codel::Color {
+                            hue: other_hue,
+                            light: other_light,
+                            block_index: None,
+                            ..
+                        } = self.canvas[other_coord.1][other_coord.0]
+                        {
+                            if other_hue == hue && other_light == light {
+                                self.set_block_index(*other_coord, new_block_index);
+                                visit_list.push(*other_coord);
                             }
                         }
                     }
                 }
+                self.blocks.push(Block {
+                    codel_coordinates,
+                    hue,
+                    light,
+                    block_exit: None,
+                });
             }
         }
     }
 
+    fn set_block_index(&mut self, coord: (usize, usize), index: usize) {
+        if let Codel::Color { block_index, .. } = &mut self.canvas[coord.1][coord.0] {
+            *block_index = Some(index);
+        }
+    }
+
     fn find_exits_for_blocks(&mut self) {
         for block in self.blocks.iter_mut() {
             block.block_exit = Some(BlockExit::from_coords(&block.codel_coordinates));
@@ -410,9 +571,7 @@ fn coord_right(coord: (usize, usize), width: usize, _height: usize) -> Option<(u
     if coord.0 + 1 >= width {
         None
     } else {
-        Some {
-            0: (coord.0 + 1, coord.1),
-        }
+        Some((coord.0 + 1, coord.1))
     }
 }
 
@@ -420,9 +579,7 @@ fn coord_left(coord: (usize, usize), _width: usize, _height: usize) -> Option<(u
     if coord.0 == 0 {
         None
     } else {
-        Some {
-            0: (coord.0 - 1, coord.1),
-        }
+        Some((coord.0 - 1, coord.1))
     }
 }
 
@@ -430,9 +587,7 @@ fn coord_up(coord: (usize, usize), _width: usize, _height: usize) -> Option<(usi
     if coord.1 == 0 {
         None
     } else {
-        Some {
-            0: (coord.0, coord.1 - 1),
-        }
+        Some((coord.0, coord.1 - 1))
     }
 }
 
@@ -440,189 +595,145 @@ fn coord_down(coord: (usize, usize), _width: usize, height: usize) -> Option<(us
     if coord.1 + 1 >= height {
         None
     } else {
-        Some {
-            0: (coord.0, coord.1 + 1),
-        }
+        Some((coord.0, coord.1 + 1))
     }
 }
 
-fn create_canvas<'a>(rgb_rows: Vec<Vec<(u8, u8, u8)>>, options: &CmdOptions) -> Vec<Vec<Codel>> {
+fn create_canvas(
+    rgb_rows: Vec<Vec<(u8, u8, u8)>>,
+    config: &Config,
+) -> Result<Vec<Vec<Codel>>, RpietError> {
+    let mut unknown_colors: Vec<UnknownColorUsage> = Vec::new();
     let mut canvas = Vec::with_capacity(rgb_rows.len());
     for (y, rgb_row) in rgb_rows.into_iter().enumerate() {
         let mut codels = Vec::with_capacity(rgb_row.len());
         for (x, rgb) in rgb_row.into_iter().enumerate() {
-            codels.push(rgb_to_codel(rgb, x, y, options.unknown_white));
+            let color = match config.palette.lookup(rgb) {
+                Some(color) => color,
+                None => {
+                    match unknown_colors.iter_mut().find(|usage| usage.color == rgb) {
+                        Some(usage) => usage.count += 1,
+                        None => unknown_colors.push(UnknownColorUsage {
+                            color: rgb,
+                            count: 1,
+                            first_position: (x, y),
+                        }),
+                    }
+                    match config.unknown_color {
+                        UnknownColor::White => PaletteColor::White,
+                        UnknownColor::Black => PaletteColor::Black,
+                        UnknownColor::Nearest => config.palette.nearest(rgb),
+                        UnknownColor::Error => {
+                            return Err(RpietError::UnknownColor {
+                                color: rgb,
+                                position: (x, y),
+                            })
+                        }
+                    }
+                }
+            };
+            codels.push(palette_color_to_codel(color, x, y));
         }
         canvas.push(codels);
     }
-    canvas
+    warn_about_unknown_colors(&unknown_colors, config.unknown_color);
+    Ok(canvas)
 }
 
-fn rgb_to_codel<'a>(rgb: (u8, u8, u8), x: usize, y: usize, unknown_white: bool) -> Codel {
-    match rgb {
-        (0x00, 0x00, 0x00) => Codel::Black { x: x, y: y },
-        (0xFF, 0xFF, 0xFF) => Codel::White { x: x, y: y },
-        // light red
-        (0xFF, 0xC0, 0xC0) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 0,
-            light: 0,
-            block_index: None,
-        },
-        // red
-        (0xFF, 0x00, 0x00) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 0,
-            light: 1,
-            block_index: None,
-        },
-        // dark  red
-        (0xC0, 0x00, 0x00) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 0,
-            light: 2,
-            block_index: None,
-        },
-        // light yellow
-        (0xFF, 0xFF, 0xC0) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 1,
-            light: 0,
-            block_index: None,
-        },
-        // yellow
-        (0xFF, 0xFF, 0x00) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 1,
-            light: 1,
-            block_index: None,
-        },
-        // dark  yellow
-        (0xC0, 0xC0, 0x00) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 1,
-            light: 2,
-            block_index: None,
-        },
-        // light green
-        (0xC0, 0xFF, 0xC0) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 2,
-            light: 0,
-            block_index: None,
-        },
-        // green
-        (0x00, 0xFF, 0x00) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 2,
-            light: 1,
-            block_index: None,
-        },
-        // dark  green
-        (0x00, 0xC0, 0x00) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 2,
-            light: 2,
-            block_index: None,
-        },
-        // light cyan
-        (0xC0, 0xFF, 0xFF) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 3,
-            light: 0,
-            block_index: None,
-        },
-        // cyan
-        (0x00, 0xFF, 0xFF) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 3,
-            light: 1,
-            block_index: None,
-        },
-        // dark  cyan
-        (0x00, 0xC0, 0xC0) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 3,
-            light: 2,
-            block_index: None,
-        },
-        // light blue
-        (0xC0, 0xC0, 0xFF) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 4,
-            light: 0,
-            block_index: None,
-        },
-        // blue
-        (0x00, 0x00, 0xFF) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 4,
-            light: 1,
-            block_index: None,
-        },
-        // dark  blue
-        (0x00, 0x00, 0xC0) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 4,
-            light: 2,
-            block_index: None,
-        },
-        // light magenta
-        (0xFF, 0xC0, 0xFF) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 5,
-            light: 0,
-            block_index: None,
-        },
-        // magenta
-        (0xFF, 0x00, 0xFF) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 5,
-            light: 1,
-            block_index: None,
-        },
-        // dark  magenta
-        (0xC0, 0x00, 0xC0) => Codel::Color {
-            x: x,
-            y: y,
-            hue: 5,
-            light: 2,
+struct UnknownColorUsage {
+    color: (u8, u8, u8),
+    count: usize,
+    first_position: (usize, usize),
+}
+
+fn warn_about_unknown_colors(unknown_colors: &[UnknownColorUsage], unknown_color: UnknownColor) {
+    if unknown_colors.is_empty() {
+        return;
+    }
+    let codels: usize = unknown_colors.iter().map(|usage| usage.count).sum();
+    eprintln!(
+        "Warning: {} {} {}, {} read as {} (see --unknown-color):",
+        counted(codels, "codel"),
+        if codels == 1 { "has" } else { "have" },
+        counted(unknown_colors.len(), "unknown color"),
+        if codels == 1 { "it is" } else { "they are" },
+        unknown_color
+    );
+    for usage in unknown_colors.iter().take(MAX_LISTED_UNKNOWN_COLORS) {
+        let (r, g, b) = usage.color;
+        eprintln!(
+            "  #{:02X}{:02X}{:02X}: {}, first at ({}, {})",
+            r,
+            g,
+            b,
+            counted(usage.count, "codel"),
+            usage.first_position.0,
+            usage.first_position.1
+        );
+    }
+    if unknown_colors.len() > MAX_LISTED_UNKNOWN_COLORS {
+        eprintln!(
+            "  and {}",
+            counted(
+                unknown_colors.len() - MAX_LISTED_UNKNOWN_COLORS,
+                "more color"
+            )
+        );
+    }
+}
+
+// e.g. "1 codel" or "3 codels"
+fn counted(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+fn palette_color_to_codel(color: PaletteColor, x: usize, y: usize) -> Codel {
+    match color {
+        PaletteColor::Black => Codel::Black { x, y },
+        PaletteColor::White => Codel::White { x, y },
+        PaletteColor::Color { hue, light } => Codel::Color {
+            x,
+            y,
+            hue,
+            light,
             block_index: None,
         },
-        (r, g, b) => {
-            eprintln!("Parsed unknown codel color ({r}, {g}, {b}) / (#{r:02X}{g:02X}{b:02X}) at pos ({x},{y})", r=r, g=g, b=b, x=x, y=y);
-            if unknown_white {
-                Codel::White { x: x, y: y }
-            } else {
-                Codel::Black { x: x, y: y }
-            }
-        }
     }
 }
 
-impl fmt::Display for Interpreter {
+impl<N: Number> fmt::Display for Interpreter<'_, N> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Interpreter<dp: {}, cc: {}, pos: {:?} alive: {}, steps: {}, pointer_toggles_without_move: {}, stack: {:?}>",
-            self.dp, self.cc, self.current_position, self.alive, self.step_counter, self.toggled_pointers_without_move, self.stack
+            "Interpreter<dp: {}, cc: {}, pos: {:?} alive: {}, steps: {}, pointer_toggles_without_move: {}, stack: [{}]>",
+            self.dp, self.cc, self.current_position, self.alive, self.step_counter, self.toggled_pointers_without_move,
+            self.stack.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(", ")
         )
     }
 }
+
+// the streams and tracers are trait objects without a Debug representation
+impl<N: Number> fmt::Debug for Interpreter<'_, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("dp", &self.dp)
+            .field("cc", &self.cc)
+            .field("alive", &self.alive)
+            .field("stack", &self.stack)
+            .field("step_counter", &self.step_counter)
+            .field("config", &self.config)
+            .field("error", &self.error)
+            .field("canvas", &self.canvas)
+            .field("blocks", &self.blocks)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("current_position", &self.current_position)
+            .field(
+                "toggled_pointers_without_move",
+                &self.toggled_pointers_without_move,
+            )
+            .field("transitions", &self.transitions)
+            .field("last_toggled_pointer", &self.last_toggled_pointer)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,79 @@
+//! `rpiet` is an interpreter for [Piet](http://www.dangermouse.net/esoteric/piet.html),
+//! the esoteric programming language which executes images.
+//!
+//! Load an image into a canvas of RGB rows with [`create_canvas`] and run it with an [`Interpreter`]:
+//!
+//! ```no_run
+//! use std::fs::File;
+//!
+//! let config = rpiet::Config::default();
+//! let file = File::open("sample_images/hello_world_globe.png")?;
+//! let canvas = rpiet::create_canvas(&file, "hello_world_globe.png", &config)?;
+//! let mut interpreter = rpiet::Interpreter::from_rgb_rows(canvas, &config)?;
+//! while interpreter.is_alive() {
+//!     interpreter.advance();
+//! }
+//! # Ok::<(), rpiet::RpietError>(())
+//! ```
+//!
+//! Interpreters read from stdin and write to stdout by default. Use
+//! [`Interpreter::from_rgb_rows_with_io`] to run a program against other streams,
+//! e.g. to feed scripted input and capture its output in memory:
+//!
+//! ```no_run
+//! # use std::fs::File;
+//! # let config = rpiet::Config::default();
+//! # let file = File::open("sample_images/hello_world_globe.png").unwrap();
+//! # let canvas = rpiet::create_canvas(&file, "hello_world_globe.png", &config).unwrap();
+//! let mut output = Vec::new();
+//! let mut interpreter =
+//!     rpiet::Interpreter::from_rgb_rows_with_io(canvas, &config, &b"42\n"[..], &mut output)?;
+//! while interpreter.is_alive() {
+//!     interpreter.advance();
+//! }
+//! drop(interpreter);
+//! assert_eq!(String::from_utf8(output).unwrap(), "Hello, world!\n");
+//! # Ok::<(), rpiet::RpietError>(())
+//! ```
+
+mod assemble;
+mod block;
+mod block_exit;
+mod command;
+mod compile;
+mod config;
+mod counters;
+mod disasm;
+mod error;
+mod grid;
+mod interpreter;
+mod lint;
+mod number;
+mod palette;
+mod render;
+mod state_graph;
+mod streams;
+mod trace;
+mod transition;
+mod utils;
+
+pub use assemble::assemble;
+pub use block::Block;
+pub use block_exit::BlockExit;
+pub use command::Command;
+pub use compile::{compile, CompileTarget};
+pub use config::{ArithmeticPolicy, CodelSize, Compat, Config, UnknownColor};
+pub use counters::{CodelChooser, DirectionPointer};
+pub use disasm::disassemble;
+pub use error::RpietError;
+pub use grid::canvas_from_grid;
+pub use interpreter::Interpreter;
+pub use lint::{lint, LintIssue, LintKind};
+pub use number::Number;
+pub use palette::{Palette, PaletteColor};
+pub use render::TraceRenderer;
+pub use trace::{Event, JsonLinesTracer, Step, Tracer};
+pub use utils::{create_canvas, detect_codel_size, write_png, RgbRows};
+
+#[cfg(feature = "bignum")]
+pub use num_bigint::BigInt;
@@ -0,0 +1,439 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+
+use crate::config::{CodelSize, Config, UnknownColor};
+use crate::error::RpietError;
+use crate::interpreter::Interpreter;
+use crate::palette::PaletteColor;
+use crate::state_graph::{Location, State, StateGraph};
+use crate::transition::POINTER_STATES;
+use crate::utils::{detect_codel_size, most_common_codel_size, RgbRows};
+
+/*
+ * The kinds of problems `lint` finds, in the order they are reported:
+ * squares of pixels which should form one codel but have several colors, colors
+ * outside of the palette, lonely codels which look like anti-aliasing artifacts,
+ * color blocks the program can never enter, slides through white which go around in
+ * circles until they get trapped and programs which cannot end at all.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintKind {
+    CodelSize,
+    UnknownColor,
+    StrayCodel,
+    UnreachableBlock,
+    WhiteLoop,
+    NeverEnds,
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LintKind::CodelSize => write!(f, "codel-size"),
+            LintKind::UnknownColor => write!(f, "unknown-color"),
+            LintKind::StrayCodel => write!(f, "stray-codel"),
+            LintKind::UnreachableBlock => write!(f, "unreachable-block"),
+            LintKind::WhiteLoop => write!(f, "white-loop"),
+            LintKind::NeverEnds => write!(f, "never-ends"),
+        }
+    }
+}
+
+/// A problem found by [`lint`], `position` is the codel it was found at.
+///
+/// Displays as `x:y: kind: message`, [`LintIssue::to_json`] formats it as a JSON object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub kind: LintKind,
+    pub position: (usize, usize),
+    pub message: String,
+}
+
+impl LintIssue {
+    fn new(kind: LintKind, position: (usize, usize), message: String) -> LintIssue {
+        LintIssue {
+            kind,
+            position,
+            message,
+        }
+    }
+
+    /// Formats the issue as a JSON object on a single line.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"kind\":\"{}\",\"x\":{},\"y\":{},\"message\":\"{}\"}}",
+            self.kind,
+            self.position.0,
+            self.position.1,
+            self.message.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    }
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.position.0, self.position.1, self.kind, self.message
+        )
+    }
+}
+
+/// Looks for likely mistakes in an image without running it.
+///
+/// `pixels` are the pixels of the whole image, e.g. read by [`crate::create_canvas`] with
+/// a codel size of 1. Codel size, palette and the handling of unknown colors are taken from
+/// `config` like for a run. The control flow is checked on every path the program may take,
+/// including both outcomes of every POINTER and SWITCH command.
+pub fn lint(pixels: RgbRows, config: &Config) -> Result<Vec<LintIssue>, RpietError> {
+    if pixels.is_empty() || pixels[0].is_empty() {
+        return Err(RpietError::EmptyImage);
+    }
+    let (width, height) = (pixels[0].len(), pixels.len());
+    let codel_size = match config.codel_size {
+        CodelSize::Fixed(codel_size) => codel_size,
+        CodelSize::Auto => detect_codel_size(&pixels.concat(), width),
+    };
+    if codel_size == 0 || width % codel_size as usize != 0 || height % codel_size as usize != 0 {
+        return Err(RpietError::CodelSizeMismatch {
+            codel_size,
+            width: width as u32,
+            height: height as u32,
+        });
+    }
+
+    let mut issues = check_codel_grid(&pixels, codel_size, config.codel_size);
+    let canvas = pixels
+        .iter()
+        .step_by(codel_size as usize)
+        .map(|row| row.iter().step_by(codel_size as usize).copied().collect())
+        .collect::<RgbRows>();
+    issues.extend(check_unknown_colors(&canvas, config));
+    let stray_codels = check_stray_codels(&canvas, config);
+    let stray_positions = stray_codels
+        .iter()
+        .map(|issue| issue.position)
+        .collect::<HashSet<_>>();
+    issues.extend(stray_codels);
+    let interpreter = Interpreter::<i64>::new(
+        resolve_unknown_colors(&canvas, config),
+        config,
+        io::empty(),
+        io::sink(),
+    )?;
+    issues.extend(check_control_flow(&interpreter, &stray_positions));
+    issues.sort_by_key(|issue| (issue.kind, issue.position.1, issue.position.0));
+    Ok(issues)
+}
+
+// squares of pixels which should be a single codel but have several colors
+fn check_codel_grid(pixels: &RgbRows, codel_size: u32, configured: CodelSize) -> Vec<LintIssue> {
+    let (width, height) = (pixels[0].len(), pixels.len());
+    // a detected codel size always fits, but a few stray pixels may have forced it down to 1
+    let (grid_size, detected) = match configured {
+        CodelSize::Fixed(_) => (codel_size as usize, false),
+        CodelSize::Auto if codel_size == 1 => (
+            most_common_codel_size(&pixels.concat(), width) as usize,
+            true,
+        ),
+        CodelSize::Auto => return Vec::new(),
+    };
+    if grid_size <= 1 {
+        return Vec::new();
+    }
+
+    let mut issues = Vec::new();
+    for top in (0..height).step_by(grid_size) {
+        for left in (0..width).step_by(grid_size) {
+            let mut colors = Vec::new();
+            for row in pixels.iter().skip(top).take(grid_size) {
+                for &rgb in row.iter().skip(left).take(grid_size) {
+                    if !colors.contains(&rgb) {
+                        colors.push(rgb);
+                    }
+                }
+            }
+            if colors.len() == 1 {
+                continue;
+            }
+            let position = (left / codel_size as usize, top / codel_size as usize);
+            let message = if detected {
+                format!(
+                    "the {size}x{size} pixels at ({}, {}) have {} colors while most of the image uses codels of {size} pixels, so the codel size was detected as 1",
+                    left,
+                    top,
+                    colors.len(),
+                    size = grid_size
+                )
+            } else {
+                format!(
+                    "the {size}x{size} pixels of this codel have {} colors, it is read as {}",
+                    colors.len(),
+                    hex(colors[0]),
+                    size = grid_size
+                )
+            };
+            issues.push(LintIssue::new(LintKind::CodelSize, position, message));
+        }
+    }
+    issues
+}
+
+fn check_unknown_colors(canvas: &RgbRows, config: &Config) -> Vec<LintIssue> {
+    // each unknown color with the number of its codels and the first of them
+    let mut unknown_colors = Vec::new();
+    for (y, row) in canvas.iter().enumerate() {
+        for (x, &rgb) in row.iter().enumerate() {
+            if config.palette.lookup(rgb).is_some() {
+                continue;
+            }
+            match unknown_colors
+                .iter_mut()
+                .find(|(color, _, _)| *color == rgb)
+            {
+                Some((_, count, _)) => *count += 1,
+                None => unknown_colors.push((rgb, 1, (x, y))),
+            }
+        }
+    }
+    unknown_colors
+        .into_iter()
+        .map(|(rgb, count, first_position)| {
+            let codels = if count == 1 { "codel is" } else { "codels are" };
+            let reading = match config.unknown_color {
+                UnknownColor::Error => String::from("an error when running the image"),
+                _ => read_as(rgb, config).to_string(),
+            };
+            let message = format!(
+                "{} is no color of the palette, its {} {} read as {}",
+                hex(rgb),
+                count,
+                codels,
+                reading
+            );
+            LintIssue::new(LintKind::UnknownColor, first_position, message)
+        })
+        .collect()
+}
+
+// replaces unknown colors the way the interpreter reads them, without warning about them again
+fn resolve_unknown_colors(canvas: &RgbRows, config: &Config) -> RgbRows {
+    canvas
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&rgb| match config.palette.lookup(rgb) {
+                    Some(_) => rgb,
+                    None => config.palette.rgb(read_as(rgb, config)).unwrap_or(rgb),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// the color the interpreter reads a pixel as, images with unknown colors are linted as if
+// they were white when the interpreter refuses to run them
+fn read_as(rgb: (u8, u8, u8), config: &Config) -> PaletteColor {
+    if let Some(color) = config.palette.lookup(rgb) {
+        return color;
+    }
+    match config.unknown_color {
+        UnknownColor::Black => PaletteColor::Black,
+        UnknownColor::Nearest => config.palette.nearest(rgb),
+        UnknownColor::White | UnknownColor::Error => PaletteColor::White,
+    }
+}
+
+// lonely codels whose color does not quite match the palette, like the blended
+// pixels anti-aliasing leaves along the edges of color blocks
+fn check_stray_codels(canvas: &RgbRows, config: &Config) -> Vec<LintIssue> {
+    let exact_palette = config.palette.clone().with_tolerance(0);
+    let mut issues = Vec::new();
+    for (y, row) in canvas.iter().enumerate() {
+        for (x, &rgb) in row.iter().enumerate() {
+            if exact_palette.lookup(rgb).is_some() {
+                continue;
+            }
+            let neighbors = [
+                y.checked_sub(1).map(|y| (x, y)),
+                Some((x, y + 1)),
+                x.checked_sub(1).map(|x| (x, y)),
+                Some((x + 1, y)),
+            ];
+            let lonely = neighbors
+                .iter()
+                .flatten()
+                .all(|&(x, y)| canvas.get(y).and_then(|row| row.get(x)) != Some(&rgb));
+            if !lonely {
+                continue;
+            }
+            let message = format!(
+                "a single codel of {}, which is read as {}, is likely left over from anti-aliasing",
+                hex(rgb),
+                read_as(rgb, config)
+            );
+            issues.push(LintIssue::new(LintKind::StrayCodel, (x, y), message));
+        }
+    }
+    issues
+}
+
+// `stray_codels` are not reported again as unreachable blocks
+fn check_control_flow(
+    interpreter: &Interpreter<i64>,
+    stray_codels: &HashSet<(usize, usize)>,
+) -> Vec<LintIssue> {
+    let graph = StateGraph::new(interpreter);
+    let start = graph.start_state(interpreter);
+    let successors = graph.reachable_states(start);
+    let entered_blocks = successors
+        .keys()
+        .filter_map(|state| match graph.locations[state.location] {
+            Location::Block(block_index) => Some(block_index),
+            Location::Codel(_) => None,
+        })
+        .collect::<HashSet<_>>();
+    let mut issues = Vec::new();
+
+    for (block_index, block) in interpreter.blocks().iter().enumerate() {
+        let position = block.codel_coordinates[0];
+        let stray = block.size() == 1 && stray_codels.contains(&position);
+        if entered_blocks.contains(&block_index) || stray {
+            continue;
+        }
+        let message = format!(
+            "{} is never entered",
+            Location::Block(block_index).describe(interpreter)
+        );
+        issues.push(LintIssue::new(
+            LintKind::UnreachableBlock,
+            position,
+            message,
+        ));
+    }
+
+    if graph.compat.slides_turn_in_white() {
+        issues.extend(check_trapped_slides(interpreter, &graph, &successors));
+    } else {
+        issues.extend(check_white_loops(&graph, &successors));
+    }
+
+    // walks the state graph backwards from the states which end the program, blocks
+    // which cannot be left and slides which get trapped
+    let mut predecessors: HashMap<_, Vec<_>> = HashMap::new();
+    for (&state, next_states) in successors.iter() {
+        for &next_state in next_states.iter() {
+            predecessors.entry(next_state).or_default().push(state);
+        }
+    }
+    let mut pending = successors
+        .iter()
+        .filter(|(_, next_states)| next_states.is_empty())
+        .map(|(&state, _)| state)
+        .collect::<Vec<_>>();
+    let mut can_end = HashSet::new();
+    while let Some(state) = pending.pop() {
+        if can_end.insert(state) {
+            pending.extend(predecessors.get(&state).into_iter().flatten());
+        }
+    }
+    if !can_end.contains(&start) {
+        let message = format!(
+            "the program never ends, none of its {} reachable states leads to a block which cannot be left or to a trapped slide",
+            successors.len()
+        );
+        issues.push(LintIssue::new(
+            LintKind::NeverEnds,
+            interpreter.current_position(),
+            message,
+        ));
+    }
+    issues
+}
+
+// a slide through white which goes around in circles traps the interpreter and ends the program
+fn check_trapped_slides(
+    interpreter: &Interpreter<i64>,
+    graph: &StateGraph,
+    successors: &HashMap<State, Vec<State>>,
+) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut traps = HashMap::new();
+    for state in successors.keys() {
+        let edge = match graph.edges[state.location][state.pointer] {
+            Some(edge) if !edge.transition.reached_new_block => edge,
+            _ => continue,
+        };
+        let (dp, cc) = POINTER_STATES[state.pointer];
+        let slide = format!(
+            "{} with dp={},cc={}",
+            graph.locations[state.location].describe(interpreter),
+            dp,
+            cc
+        );
+        let slides = traps
+            .entry(edge.transition.new_position)
+            .or_insert_with(Vec::new);
+        if !slides.contains(&slide) {
+            slides.push(slide);
+        }
+    }
+    for (position, mut slides) in traps.into_iter() {
+        slides.sort();
+        let message = format!(
+            "sliding through white goes around in circles and traps the program here, coming from {}",
+            slides.join(" or ")
+        );
+        issues.push(LintIssue::new(LintKind::WhiteLoop, position, message));
+    }
+    issues
+}
+
+// with `Compat::Rpiet` white codels have a single next state, a walk through them which
+// comes back to a state it already took never reaches a color block again
+fn check_white_loops(
+    graph: &StateGraph,
+    successors: &HashMap<State, Vec<State>>,
+) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let is_white = |state: &State| matches!(graph.locations[state.location], Location::Codel(_));
+    let mut checked = HashSet::new();
+    for &first_state in successors.keys() {
+        let mut path = Vec::new();
+        let mut state = first_state;
+        while is_white(&state) && checked.insert(state) {
+            path.push(state);
+            match successors[&state][..] {
+                [next_state] => state = next_state,
+                _ => {
+                    path.clear();
+                    break;
+                }
+            }
+        }
+        let loop_start = match path.iter().position(|&path_state| path_state == state) {
+            Some(loop_start) => loop_start,
+            None => continue,
+        };
+        let codels = path[loop_start..]
+            .iter()
+            .filter_map(|state| match graph.locations[state.location] {
+                Location::Codel(position) => Some(position),
+                Location::Block(_) => None,
+            })
+            .collect::<HashSet<_>>();
+        let position = *codels.iter().min_by_key(|&&(x, y)| (y, x)).unwrap();
+        let message = format!(
+            "sliding through white gets stuck in a loop over {} white codels and never ends",
+            codels.len()
+        );
+        issues.push(LintIssue::new(LintKind::WhiteLoop, position, message));
+    }
+    issues
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02X}{:02X}{:02X}", r, g, b)
+}
@@ -1,44 +1,203 @@
 extern crate clap;
-extern crate png;
+extern crate rpiet;
 
-mod block;
-mod block_exit;
 mod cmd_options;
-mod command;
-mod counters;
-mod interpreter;
-mod utils;
-
-use cmd_options::{clap_options, cmd_options};
-use interpreter::Interpreter;
-use std::fs::File;
+mod debugger;
+
+use cmd_options::{clap_options, cmd_options, CmdOptions, LintFormat, Mode, NumberBackend};
+use debugger::{Debugger, SharedInput};
+use rpiet::{
+    CodelSize, CompileTarget, Config, Interpreter, JsonLinesTracer, Number, Palette, RpietError,
+    TraceRenderer,
+};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::num::Wrapping;
+use std::path::Path;
 use std::process;
 
 fn main() {
     let clap_args = &clap_options();
-    let options = cmd_options(&clap_args);
+    let options = cmd_options(clap_args);
+
+    if let Err(e) = run(&options) {
+        eprintln!("Application error: {}", e);
+        process::exit(exit_code(&e));
+    }
+}
+
+fn run(options: &CmdOptions) -> Result<(), RpietError> {
+    if let Mode::Assemble = options.mode {
+        return assemble(options);
+    }
+    let mut config = options.config.clone();
+    if let Some(palette_path) = options.palette_path {
+        config.palette = Palette::parse(&fs::read_to_string(palette_path)?)?;
+    }
+    if let Some(color_tolerance) = options.color_tolerance {
+        config.palette = config.palette.with_tolerance(color_tolerance);
+    }
+    match options.mode {
+        Mode::Compile(target) => return compile(options, &config, target),
+        Mode::Disasm => return disassemble(options, &config),
+        Mode::Lint(ref format) => return lint(options, &config, format),
+        _ => (),
+    }
+    match options.number_backend {
+        NumberBackend::Checked => run_with_numbers::<i64>(options, &config),
+        NumberBackend::Wrapping => run_with_numbers::<Wrapping<i64>>(options, &config),
+        #[cfg(feature = "bignum")]
+        NumberBackend::BigInt => run_with_numbers::<rpiet::BigInt>(options, &config),
+    }
+}
 
-    if options.verbose {
+fn run_with_numbers<N: Number>(options: &CmdOptions, config: &Config) -> Result<(), RpietError> {
+    if config.verbose {
         eprintln!("Reading file {}", options.file_path);
     }
 
-    let file = File::open(options.file_path);
-    let file = match file {
-        Ok(file) => file,
-        Err(e) => {
-            println!("Application error: {}", e);
-            process::exit(1);
+    let file = File::open(options.file_path)?;
+    let canvas = rpiet::create_canvas(&file, options.file_path, config)?;
+    let render_canvas = options.render_trace_path.map(|_| canvas.clone());
+    // the debugger reads its commands from stdin, too, so it shares one buffer with the program
+    let shared_input = match options.mode {
+        Mode::Debug => Some(SharedInput::new(BufReader::new(io::stdin()))),
+        _ => None,
+    };
+    let mut interpreter = match &shared_input {
+        Some(shared_input) => {
+            Interpreter::<N>::new(canvas, config, shared_input.clone(), io::stdout())?
         }
+        None => Interpreter::<N>::new(canvas, config, BufReader::new(io::stdin()), io::stdout())?,
     };
-    let canvas = utils::create_canvas(&file, &options);
-    let mut interpreter = Interpreter::from_rgb_rows(canvas, &options);
-    if options.verbose {
-        eprintln!("Start State:   {}", interpreter);
-    }
-    while interpreter.is_alive() {
-        interpreter.advance();
-        if options.verbose {
-            eprintln!("Current State: {}", interpreter);
+    if let (Some(render_trace_path), Some(render_canvas)) =
+        (options.render_trace_path, render_canvas)
+    {
+        let image_file = BufWriter::new(File::create(render_trace_path)?);
+        let renderer = TraceRenderer::new(render_canvas, &interpreter, image_file);
+        interpreter.add_tracer(Box::new(renderer));
+    }
+    if let Some(trace_path) = options.trace_path {
+        let trace_file = BufWriter::new(File::create(trace_path)?);
+        interpreter.add_tracer(Box::new(JsonLinesTracer::new(trace_file)));
+    }
+    match options.mode {
+        Mode::Debug => {
+            let shared_input = shared_input.expect("the input is shared in debug mode");
+            Debugger::new().run(&mut interpreter, shared_input)?
         }
+        Mode::Compile(_) | Mode::Disasm | Mode::Lint(_) | Mode::Assemble => {
+            unreachable!("the image is not run in this mode")
+        }
+        Mode::Run => {
+            if config.verbose {
+                eprintln!("Start State:   {}", interpreter);
+            }
+            while interpreter.is_alive() {
+                interpreter.advance();
+                if config.verbose {
+                    eprintln!("Current State: {}", interpreter);
+                }
+            }
+        }
+    }
+    interpreter.finish_tracing()?;
+    match interpreter.take_error() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+fn compile(options: &CmdOptions, config: &Config, target: CompileTarget) -> Result<(), RpietError> {
+    if config.verbose {
+        eprintln!("Compiling file {} to {}", options.file_path, target);
+    }
+
+    let file = File::open(options.file_path)?;
+    let canvas = rpiet::create_canvas(&file, options.file_path, config)?;
+    let interpreter = Interpreter::<i64>::new(canvas, config, io::empty(), io::sink())?;
+    let code = rpiet::compile(&interpreter, target, &source_name(options.file_path));
+    match options.output_path {
+        Some(output_path) => fs::write(output_path, code)?,
+        None => io::stdout().write_all(code.as_bytes())?,
+    }
+    Ok(())
+}
+
+fn disassemble(options: &CmdOptions, config: &Config) -> Result<(), RpietError> {
+    if config.verbose {
+        eprintln!("Disassembling file {}", options.file_path);
+    }
+
+    let file = File::open(options.file_path)?;
+    let canvas = rpiet::create_canvas(&file, options.file_path, config)?;
+    let interpreter = Interpreter::<i64>::new(canvas, config, io::empty(), io::sink())?;
+    let listing = rpiet::disassemble(&interpreter, &source_name(options.file_path));
+    io::stdout().write_all(listing.as_bytes())?;
+    Ok(())
+}
+
+fn lint(options: &CmdOptions, config: &Config, format: &LintFormat) -> Result<(), RpietError> {
+    if config.verbose {
+        eprintln!("Linting file {}", options.file_path);
+    }
+
+    // the lint compares the pixels of each codel, so it reads every pixel on its own
+    let pixel_config = Config {
+        codel_size: CodelSize::Fixed(1),
+        ..config.clone()
+    };
+    let file = File::open(options.file_path)?;
+    let pixels = rpiet::create_canvas(&file, options.file_path, &pixel_config)?;
+    let issues = rpiet::lint(pixels, config)?;
+    let mut output = BufWriter::new(io::stdout());
+    for issue in issues.iter() {
+        match format {
+            LintFormat::Text => writeln!(output, "{}:{}", options.file_path, issue)?,
+            LintFormat::Json => writeln!(output, "{}", issue.to_json())?,
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+fn assemble(options: &CmdOptions) -> Result<(), RpietError> {
+    if options.config.verbose {
+        eprintln!("Assembling file {}", options.file_path);
+    }
+
+    let source = fs::read_to_string(options.file_path)?;
+    let canvas = rpiet::assemble(&source)?;
+    let codel_size = match options.config.codel_size {
+        CodelSize::Fixed(codel_size) => codel_size,
+        CodelSize::Auto => 1,
+    };
+    let output_path = options.output_path.unwrap();
+    rpiet::write_png(
+        &canvas,
+        codel_size,
+        BufWriter::new(File::create(output_path)?),
+    )?;
+    Ok(())
+}
+
+// the file name without its directories, for headers of generated files
+fn source_name(file_path: &str) -> String {
+    Path::new(file_path)
+        .file_name()
+        .map_or(file_path.into(), |name| name.to_string_lossy().into_owned())
+}
+
+fn exit_code(error: &RpietError) -> i32 {
+    match error {
+        RpietError::Io(_) => 2,
+        RpietError::Decode(_) | RpietError::UnsupportedFormat(_) => 3,
+        RpietError::UnsupportedColorType(_) => 4,
+        RpietError::CodelSizeMismatch { .. } => 5,
+        RpietError::EmptyImage => 6,
+        RpietError::ArithmeticOverflow(_) => 7,
+        RpietError::UnknownColor { .. } => 8,
+        RpietError::InvalidPalette { .. } => 9,
+        RpietError::InvalidAssembly { .. } | RpietError::InvalidGrid { .. } => 10,
     }
 }
@@ -0,0 +1,311 @@
+use std::fmt;
+use std::num::Wrapping;
+
+/*
+ * Number abstracts over the values on the Piet stack, so the interpreter can run
+ * with different number backends:
+ *
+ * - `i64` follows the interpreter's `ArithmeticPolicy` on overflow: a command which
+ *   would overflow is ignored (like commands which do not find enough values on the
+ *   stack), wraps around, saturates at `i64::MIN`/`i64::MAX` or halts the program,
+ * - `Wrapping<i64>` wraps around on overflow, whatever the policy asks for,
+ * - `BigInt` (with the `bignum` feature) never overflows, so the policy never applies.
+ *
+ * The checked operations return `None` on overflow, which ignores or halts, the
+ * wrapping and saturating variants are used instead under `Wrap` and `Saturate`.
+ * All of them are required, so an implementation cannot panic where the
+ * interpreter expects a result. Division and modulo by zero are handled by the caller.
+ */
+pub trait Number: Clone + PartialOrd + fmt::Display + fmt::Debug {
+    fn from_i64(value: i64) -> Self;
+    /// Returns the value as `i64`, or `None` if it does not fit.
+    fn to_i64(&self) -> Option<i64>;
+    fn parse(text: &str) -> Option<Self>;
+    fn is_zero(&self) -> bool;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_sub(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn checked_div(&self, other: &Self) -> Option<Self>;
+    fn checked_rem_euclid(&self, other: &Self) -> Option<Self>;
+
+    // Implementations choose what wrapping and saturating mean for them,
+    // numbers which never overflow return the exact result.
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_sub(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    fn wrapping_div(&self, other: &Self) -> Self;
+    fn wrapping_rem_euclid(&self, other: &Self) -> Self;
+    fn saturating_add(&self, other: &Self) -> Self;
+    fn saturating_sub(&self, other: &Self) -> Self;
+    fn saturating_mul(&self, other: &Self) -> Self;
+    fn saturating_div(&self, other: &Self) -> Self;
+    fn saturating_rem_euclid(&self, other: &Self) -> Self;
+    /// The remainder of dividing by `modulus`, with the sign of `self` (like `%` on `i64`).
+    fn rem_i64(&self, modulus: i64) -> i64;
+}
+
+impl Number for i64 {
+    fn from_i64(value: i64) -> i64 {
+        value
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn parse(text: &str) -> Option<i64> {
+        text.parse::<i64>().ok()
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn checked_add(&self, other: &i64) -> Option<i64> {
+        i64::checked_add(*self, *other)
+    }
+
+    fn checked_sub(&self, other: &i64) -> Option<i64> {
+        i64::checked_sub(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &i64) -> Option<i64> {
+        i64::checked_mul(*self, *other)
+    }
+
+    fn checked_div(&self, other: &i64) -> Option<i64> {
+        i64::checked_div(*self, *other)
+    }
+
+    fn checked_rem_euclid(&self, other: &i64) -> Option<i64> {
+        i64::checked_rem_euclid(*self, *other)
+    }
+
+    fn wrapping_add(&self, other: &i64) -> i64 {
+        i64::wrapping_add(*self, *other)
+    }
+
+    fn wrapping_sub(&self, other: &i64) -> i64 {
+        i64::wrapping_sub(*self, *other)
+    }
+
+    fn wrapping_mul(&self, other: &i64) -> i64 {
+        i64::wrapping_mul(*self, *other)
+    }
+
+    fn wrapping_div(&self, other: &i64) -> i64 {
+        i64::wrapping_div(*self, *other)
+    }
+
+    fn wrapping_rem_euclid(&self, other: &i64) -> i64 {
+        i64::wrapping_rem_euclid(*self, *other)
+    }
+
+    fn saturating_add(&self, other: &i64) -> i64 {
+        i64::saturating_add(*self, *other)
+    }
+
+    fn saturating_sub(&self, other: &i64) -> i64 {
+        i64::saturating_sub(*self, *other)
+    }
+
+    fn saturating_mul(&self, other: &i64) -> i64 {
+        i64::saturating_mul(*self, *other)
+    }
+
+    fn saturating_div(&self, other: &i64) -> i64 {
+        i64::saturating_div(*self, *other)
+    }
+
+    // the only overflowing case is `i64::MIN` modulo -1, whose exact result 0 is in bounds
+    fn saturating_rem_euclid(&self, other: &i64) -> i64 {
+        i64::wrapping_rem_euclid(*self, *other)
+    }
+
+    fn rem_i64(&self, modulus: i64) -> i64 {
+        self % modulus
+    }
+}
+
+impl Number for Wrapping<i64> {
+    fn from_i64(value: i64) -> Wrapping<i64> {
+        Wrapping(value)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(self.0)
+    }
+
+    fn parse(text: &str) -> Option<Wrapping<i64>> {
+        text.parse::<i64>().ok().map(Wrapping)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn checked_add(&self, other: &Wrapping<i64>) -> Option<Wrapping<i64>> {
+        Some(self + other)
+    }
+
+    fn checked_sub(&self, other: &Wrapping<i64>) -> Option<Wrapping<i64>> {
+        Some(self - other)
+    }
+
+    fn checked_mul(&self, other: &Wrapping<i64>) -> Option<Wrapping<i64>> {
+        Some(self * other)
+    }
+
+    fn checked_div(&self, other: &Wrapping<i64>) -> Option<Wrapping<i64>> {
+        Some(Wrapping(self.0.wrapping_div(other.0)))
+    }
+
+    fn checked_rem_euclid(&self, other: &Wrapping<i64>) -> Option<Wrapping<i64>> {
+        Some(Wrapping(self.0.wrapping_rem_euclid(other.0)))
+    }
+
+    fn wrapping_add(&self, other: &Wrapping<i64>) -> Wrapping<i64> {
+        self + other
+    }
+
+    fn wrapping_sub(&self, other: &Wrapping<i64>) -> Wrapping<i64> {
+        self - other
+    }
+
+    fn wrapping_mul(&self, other: &Wrapping<i64>) -> Wrapping<i64> {
+        self * other
+    }
+
+    fn wrapping_div(&self, other: &Wrapping<i64>) -> Wrapping<i64> {
+        Wrapping(self.0.wrapping_div(other.0))
+    }
+
+    fn wrapping_rem_euclid(&self, other: &Wrapping<i64>) -> Wrapping<i64> {
+        Wrapping(self.0.wrapping_rem_euclid(other.0))
+    }
+
+    // the backend wraps around, whatever the arithmetic policy asks for
+    fn saturating_add(&self, other: &Wrapping<i64>) -> Wrapping<i64> {
+        self + other
+    }
+
+    fn saturating_sub(&self, other: &Wrapping<i64>) -> Wrapping<i64> {
+        self - other
+    }
+
+    fn saturating_mul(&self, other: &Wrapping<i64>) -> Wrapping<i64> {
+        self * other
+    }
+
+    fn saturating_div(&self, other: &Wrapping<i64>) -> Wrapping<i64> {
+        Wrapping(self.0.wrapping_div(other.0))
+    }
+
+    fn saturating_rem_euclid(&self, other: &Wrapping<i64>) -> Wrapping<i64> {
+        Wrapping(self.0.wrapping_rem_euclid(other.0))
+    }
+
+    fn rem_i64(&self, modulus: i64) -> i64 {
+        self.0 % modulus
+    }
+}
+
+#[cfg(feature = "bignum")]
+mod bignum {
+    use num_bigint::BigInt;
+    use num_traits::{Signed, ToPrimitive, Zero};
+
+    use super::Number;
+
+    impl Number for BigInt {
+        fn from_i64(value: i64) -> BigInt {
+            BigInt::from(value)
+        }
+
+        fn to_i64(&self) -> Option<i64> {
+            ToPrimitive::to_i64(self)
+        }
+
+        fn parse(text: &str) -> Option<BigInt> {
+            text.parse::<BigInt>().ok()
+        }
+
+        fn is_zero(&self) -> bool {
+            Zero::is_zero(self)
+        }
+
+        fn checked_add(&self, other: &BigInt) -> Option<BigInt> {
+            Some(self + other)
+        }
+
+        fn checked_sub(&self, other: &BigInt) -> Option<BigInt> {
+            Some(self - other)
+        }
+
+        fn checked_mul(&self, other: &BigInt) -> Option<BigInt> {
+            Some(self * other)
+        }
+
+        fn checked_div(&self, other: &BigInt) -> Option<BigInt> {
+            Some(self / other)
+        }
+
+        fn checked_rem_euclid(&self, other: &BigInt) -> Option<BigInt> {
+            Some(rem_euclid(self, other))
+        }
+
+        // big integers never overflow, so wrapping and saturating give the exact result
+        fn wrapping_add(&self, other: &BigInt) -> BigInt {
+            self + other
+        }
+
+        fn wrapping_sub(&self, other: &BigInt) -> BigInt {
+            self - other
+        }
+
+        fn wrapping_mul(&self, other: &BigInt) -> BigInt {
+            self * other
+        }
+
+        fn wrapping_div(&self, other: &BigInt) -> BigInt {
+            self / other
+        }
+
+        fn wrapping_rem_euclid(&self, other: &BigInt) -> BigInt {
+            rem_euclid(self, other)
+        }
+
+        fn saturating_add(&self, other: &BigInt) -> BigInt {
+            self + other
+        }
+
+        fn saturating_sub(&self, other: &BigInt) -> BigInt {
+            self - other
+        }
+
+        fn saturating_mul(&self, other: &BigInt) -> BigInt {
+            self * other
+        }
+
+        fn saturating_div(&self, other: &BigInt) -> BigInt {
+            self / other
+        }
+
+        fn saturating_rem_euclid(&self, other: &BigInt) -> BigInt {
+            rem_euclid(self, other)
+        }
+
+        fn rem_i64(&self, modulus: i64) -> i64 {
+            ToPrimitive::to_i64(&(self % modulus)).unwrap()
+        }
+    }
+
+    fn rem_euclid(a: &BigInt, b: &BigInt) -> BigInt {
+        let remainder = a % b;
+        if remainder.is_negative() {
+            remainder + b.abs()
+        } else {
+            remainder
+        }
+    }
+}
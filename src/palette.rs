@@ -0,0 +1,366 @@
+use std::fmt;
+
+use crate::error::RpietError;
+
+const HUE_NAMES: [&str; 6] = ["red", "yellow", "green", "cyan", "blue", "magenta"];
+const LIGHT_NAMES: [&str; 3] = ["light", "", "dark"];
+
+/*
+ * The meaning of a palette entry: black, white or a color with a hue
+ * (0 = red, 1 = yellow, 2 = green, 3 = cyan, 4 = blue, 5 = magenta) and a
+ * lightness (0 = light, 1 = normal, 2 = dark).
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteColor {
+    Black,
+    White,
+    Color { hue: u8, light: u8 },
+}
+
+impl fmt::Display for PaletteColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteColor::Black => write!(f, "black"),
+            PaletteColor::White => write!(f, "white"),
+            PaletteColor::Color { hue, light } => match LIGHT_NAMES[*light as usize] {
+                "" => write!(f, "{}", HUE_NAMES[*hue as usize]),
+                light => write!(f, "{} {}", light, HUE_NAMES[*hue as usize]),
+            },
+        }
+    }
+}
+
+/*
+ * A Palette maps RGB values to black, white and the 18 Piet colors.
+ *
+ * A pixel matches an entry if each of its channels differs by at most
+ * `tolerance` from the entry's RGB value; the closest entry wins. Several RGB
+ * values may map to the same Piet color, e.g. for images which use slightly
+ * different shades, but an RGB value cannot have two meanings.
+ *
+ * Palettes are read from a small text format, one entry per line:
+ *
+ * ```text
+ * # lines starting with a hash are comments
+ * tolerance 8
+ * black     #000000
+ * white     #FFFFFF
+ * light red #FFC0C0
+ * red       #FF0000
+ * dark red  #C00000
+ * ```
+ */
+#[derive(Debug, Clone)]
+pub struct Palette {
+    entries: Vec<((u8, u8, u8), PaletteColor)>,
+    tolerance: u8,
+}
+
+impl Palette {
+    /// The 20 colors of the Piet specification, matched exactly.
+    pub fn standard() -> Palette {
+        let mut entries = vec![
+            ((0x00, 0x00, 0x00), PaletteColor::Black),
+            ((0xFF, 0xFF, 0xFF), PaletteColor::White),
+        ];
+        let hues = [
+            (0xFF, 0x00, 0x00),
+            (0xFF, 0xFF, 0x00),
+            (0x00, 0xFF, 0x00),
+            (0x00, 0xFF, 0xFF),
+            (0x00, 0x00, 0xFF),
+            (0xFF, 0x00, 0xFF),
+        ];
+        for (hue, &(r, g, b)) in hues.iter().enumerate() {
+            // light colors raise the zero channels to 0xC0, dark colors lower the full channels to 0xC0
+            let light = |c: u8| if c == 0 { 0xC0 } else { c };
+            let dark = |c: u8| if c == 0 { 0 } else { 0xC0 };
+            for (light_level, rgb) in [
+                (light(r), light(g), light(b)),
+                (r, g, b),
+                (dark(r), dark(g), dark(b)),
+            ]
+            .iter()
+            .enumerate()
+            {
+                entries.push((
+                    *rgb,
+                    PaletteColor::Color {
+                        hue: hue as u8,
+                        light: light_level as u8,
+                    },
+                ));
+            }
+        }
+        Palette {
+            entries,
+            tolerance: 0,
+        }
+    }
+
+    /// Parses a palette in the text format described above.
+    pub fn parse(text: &str) -> Result<Palette, RpietError> {
+        let mut palette = Palette {
+            entries: Vec::new(),
+            tolerance: 0,
+        };
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let error = |message: String| RpietError::InvalidPalette {
+                line: index + 1,
+                message,
+            };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            if words[0] == "tolerance" {
+                palette.tolerance = match words[1..] {
+                    [tolerance] => tolerance
+                        .parse::<u8>()
+                        .map_err(|_| error(format!("invalid tolerance {}", tolerance)))?,
+                    _ => return Err(error(String::from("expected `tolerance <0-255>`"))),
+                };
+                continue;
+            }
+            if words.len() < 2 {
+                return Err(error(String::from("expected `<color name> <RGB value>`")));
+            }
+            let (name, rgb) = words.split_at(words.len() - 1);
+            let color = parse_color_name(&name.join(" "))
+                .ok_or_else(|| error(format!("unknown color name `{}`", name.join(" "))))?;
+            let rgb_text = rgb[0];
+            let rgb = parse_rgb(rgb_text).ok_or_else(|| {
+                error(format!(
+                    "expected an RGB value like #FF0000, got `{}`",
+                    rgb_text
+                ))
+            })?;
+            // several values may mean the same color, but a value cannot mean two colors
+            if let Some(&(_, other)) = palette
+                .entries
+                .iter()
+                .find(|&&(entry, other)| entry == rgb && other != color)
+            {
+                return Err(error(format!("{} already means {}", rgb_text, other)));
+            }
+            palette.entries.push((rgb, color));
+        }
+        if palette.entries.is_empty() {
+            return Err(RpietError::InvalidPalette {
+                line: 0,
+                message: String::from("the palette does not contain any colors"),
+            });
+        }
+        Ok(palette)
+    }
+
+    pub fn tolerance(&self) -> u8 {
+        self.tolerance
+    }
+
+    /// Returns the palette with a different tolerance.
+    pub fn with_tolerance(mut self, tolerance: u8) -> Palette {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Returns what `rgb` means in this palette, or `None` if it is an unknown color.
+    pub fn lookup(&self, rgb: (u8, u8, u8)) -> Option<PaletteColor> {
+        if let Some(&(_, color)) = self.entries.iter().find(|&&(entry, _)| entry == rgb) {
+            return Some(color);
+        }
+        if self.tolerance == 0 {
+            return None;
+        }
+        self.entries
+            .iter()
+            .map(|&(entry, color)| (channel_distance(rgb, entry), color))
+            .filter(|&(distance, _)| distance <= self.tolerance)
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(_, color)| color)
+    }
+
+    /// Returns the first RGB value which means `color` in this palette.
+    pub fn rgb(&self, color: PaletteColor) -> Option<(u8, u8, u8)> {
+        self.entries
+            .iter()
+            .find(|&&(_, entry)| entry == color)
+            .map(|&(rgb, _)| rgb)
+    }
+
+    /// Returns the entry with the smallest perceived color difference to `rgb`, regardless of the tolerance.
+    pub fn nearest(&self, rgb: (u8, u8, u8)) -> PaletteColor {
+        self.entries
+            .iter()
+            .map(|&(entry, color)| (redmean_distance(rgb, entry), color))
+            .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+            .map(|(_, color)| color)
+            .unwrap_or(PaletteColor::White)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::standard()
+    }
+}
+
+fn parse_color_name(name: &str) -> Option<PaletteColor> {
+    match name {
+        "black" => return Some(PaletteColor::Black),
+        "white" => return Some(PaletteColor::White),
+        _ => (),
+    }
+    let (light, hue_name) = match name.split_once(' ') {
+        Some(("light", hue_name)) => (0, hue_name),
+        Some(("dark", hue_name)) => (2, hue_name),
+        Some(_) => return None,
+        None => (1, name),
+    };
+    let hue = HUE_NAMES.iter().position(|&hue| hue == hue_name)?;
+    Some(PaletteColor::Color {
+        hue: hue as u8,
+        light,
+    })
+}
+
+fn parse_rgb(text: &str) -> Option<(u8, u8, u8)> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |range| u8::from_str_radix(&hex[range], 16).ok();
+    Some((channel(0..2)?, channel(2..4)?, channel(4..6)?))
+}
+
+fn channel_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u8 {
+    a.0.abs_diff(b.0)
+        .max(a.1.abs_diff(b.1))
+        .max(a.2.abs_diff(b.2))
+}
+
+// the "redmean" distance, a cheap approximation of perceived color difference
+fn redmean_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    let red_mean = (a.0 as f64 + b.0 as f64) / 2.0;
+    let dr = a.0 as f64 - b.0 as f64;
+    let dg = a.1 as f64 - b.1 as f64;
+    let db = a.2 as f64 - b.2 as f64;
+    (2.0 + red_mean / 256.0) * dr * dr
+        + 4.0 * dg * dg
+        + (2.0 + (255.0 - red_mean) / 256.0) * db * db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Palette, PaletteColor};
+    use crate::error::RpietError;
+
+    const RED: PaletteColor = PaletteColor::Color { hue: 0, light: 1 };
+    const DARK_BLUE: PaletteColor = PaletteColor::Color { hue: 4, light: 2 };
+
+    fn parse_error(text: &str) -> (usize, String) {
+        match Palette::parse(text) {
+            Err(RpietError::InvalidPalette { line, message }) => (line, message),
+            other => panic!("expected a palette error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_entries_comments_and_the_tolerance() {
+        let palette = Palette::parse(
+            "# shades of an old scan\n\
+             tolerance 4\n\
+             \n\
+             red        #FF0000\n\
+             red        F01010\n\
+             dark blue  #0000c0\n\
+             black      #000000",
+        )
+        .unwrap();
+        assert_eq!(palette.tolerance(), 4);
+        assert_eq!(palette.lookup((0xFF, 0x00, 0x00)), Some(RED));
+        assert_eq!(palette.lookup((0xF0, 0x10, 0x10)), Some(RED));
+        assert_eq!(palette.lookup((0x00, 0x00, 0xC0)), Some(DARK_BLUE));
+        assert_eq!(palette.lookup((0xFF, 0xFF, 0xFF)), None);
+        assert_eq!(palette.rgb(RED), Some((0xFF, 0x00, 0x00)));
+        assert_eq!(palette.rgb(PaletteColor::White), None);
+    }
+
+    #[test]
+    fn reports_mistakes_with_their_line() {
+        assert_eq!(
+            parse_error("red #FF0000\nred #FF00"),
+            (
+                2,
+                String::from("expected an RGB value like #FF0000, got `#FF00`")
+            )
+        );
+        assert_eq!(
+            parse_error("red #GG0000"),
+            (
+                1,
+                String::from("expected an RGB value like #FF0000, got `#GG0000`")
+            )
+        );
+        assert_eq!(
+            parse_error("# comment\npink #FFC0C0"),
+            (2, String::from("unknown color name `pink`"))
+        );
+        assert_eq!(
+            parse_error("very dark red #800000"),
+            (1, String::from("unknown color name `very dark red`"))
+        );
+        assert_eq!(
+            parse_error("red"),
+            (1, String::from("expected `<color name> <RGB value>`"))
+        );
+        assert_eq!(
+            parse_error("red #FF0000\nblue #FF0000"),
+            (2, String::from("#FF0000 already means red"))
+        );
+    }
+
+    #[test]
+    fn reports_invalid_tolerances() {
+        assert_eq!(
+            parse_error("tolerance 256\nred #FF0000"),
+            (1, String::from("invalid tolerance 256"))
+        );
+        assert_eq!(
+            parse_error("tolerance -1"),
+            (1, String::from("invalid tolerance -1"))
+        );
+        assert_eq!(
+            parse_error("red #FF0000\ntolerance"),
+            (2, String::from("expected `tolerance <0-255>`"))
+        );
+        assert_eq!(
+            parse_error("tolerance 4 8"),
+            (1, String::from("expected `tolerance <0-255>`"))
+        );
+        assert_eq!(
+            parse_error("tolerance 4\n# no colors"),
+            (0, String::from("the palette does not contain any colors"))
+        );
+    }
+
+    #[test]
+    fn a_color_may_have_several_values() {
+        let palette = Palette::parse("red #FF0000\nred #FE0000\nred #FF0000").unwrap();
+        assert_eq!(palette.lookup((0xFE, 0x00, 0x00)), Some(RED));
+    }
+
+    #[test]
+    fn matches_colors_within_the_tolerance() {
+        let palette = Palette::standard().with_tolerance(8);
+        // every channel may differ by the tolerance
+        assert_eq!(palette.lookup((0xF7, 0x08, 0x08)), Some(RED));
+        assert_eq!(palette.lookup((0xF6, 0x00, 0x00)), None);
+        // the closest entry wins
+        assert_eq!(palette.lookup((0x04, 0x04, 0xC4)), Some(DARK_BLUE));
+        assert_eq!(Palette::standard().lookup((0xF7, 0x08, 0x08)), None);
+        // nearest ignores the tolerance
+        assert_eq!(Palette::standard().nearest((0xE0, 0x20, 0x20)), RED);
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::interpreter::Interpreter;
+use crate::number::Number;
+use crate::trace::{Event, Step, Tracer};
+use crate::utils::RgbRows;
+
+const MAX_IMAGE_LENGTH: usize = 2048;
+const MIN_SCALE: usize = 4;
+const MAX_SCALE: usize = 24;
+const COMMAND_COLOR: (u8, u8, u8) = (0x00, 0x00, 0x00);
+const SLIDE_COLOR: (u8, u8, u8) = (0x80, 0x80, 0x80);
+const TOGGLE_COLOR: (u8, u8, u8) = (0xFF, 0x80, 0x00);
+const GRID_COLOR: (u8, u8, u8) = (0xA0, 0xA0, 0xA0);
+const COLD_COLOR: (u8, u8, u8) = (0x40, 0x40, 0x40);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Move {
+    from: (usize, usize),
+    to: (usize, usize),
+    slide: bool,
+}
+
+/*
+ * A TraceRenderer records the path an interpreter takes and draws it on top of a
+ * scaled up copy of the canvas once tracing is finished:
+ *
+ * - solid black arrows for moves which executed a command,
+ * - dashed gray arrows for slides through white codels,
+ * - orange crosses where the interpreter toggled DP or CC,
+ * - color blocks are darkened the less often they were entered (a heatmap), so
+ *   blocks which were never visited stay dark.
+ */
+pub struct TraceRenderer<W: Write> {
+    rgb_rows: RgbRows,
+    block_indices: Vec<Vec<Option<usize>>>,
+    block_visits: Vec<u64>,
+    moves: Vec<Move>,
+    known_moves: HashSet<Move>,
+    toggles: HashSet<(usize, usize)>,
+    writer: Option<W>,
+}
+
+impl<W: Write> TraceRenderer<W> {
+    /// Creates a renderer for the given interpreter which writes a PNG image of
+    /// `rgb_rows` (the canvas the interpreter was created from) to `writer`.
+    pub fn new<N: Number>(
+        rgb_rows: RgbRows,
+        interpreter: &Interpreter<N>,
+        writer: W,
+    ) -> TraceRenderer<W> {
+        let block_indices = rgb_rows
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                (0..row.len())
+                    .map(|x| interpreter.block_index_at((x, y)))
+                    .collect()
+            })
+            .collect::<Vec<Vec<_>>>();
+        let mut block_visits = vec![0; interpreter.blocks().len()];
+        if let Some(index) = interpreter.block_index_at(interpreter.current_position()) {
+            block_visits[index] = 1;
+        }
+        TraceRenderer {
+            rgb_rows,
+            block_indices,
+            block_visits,
+            moves: Vec::new(),
+            known_moves: HashSet::new(),
+            toggles: HashSet::new(),
+            writer: Some(writer),
+        }
+    }
+
+    fn record_move(&mut self, from: (usize, usize), to: (usize, usize), slide: bool) {
+        let new_move = Move { from, to, slide };
+        if from != to && self.known_moves.insert(new_move) {
+            self.moves.push(new_move);
+        }
+    }
+
+    fn render(&self) -> Image {
+        let rows = self.rgb_rows.len();
+        let columns = self.rgb_rows.first().map_or(0, Vec::len);
+        let scale = (MAX_IMAGE_LENGTH / rows.max(columns).max(1)).clamp(MIN_SCALE, MAX_SCALE);
+        let mut image = Image::new(columns * scale, rows * scale);
+
+        let max_visits = self.block_visits.iter().cloned().max().unwrap_or(0);
+        for (y, row) in self.rgb_rows.iter().enumerate() {
+            for (x, &rgb) in row.iter().enumerate() {
+                let color = match self.block_indices[y][x] {
+                    Some(index) => {
+                        let heat = heat(self.block_visits[index], max_visits);
+                        blend(rgb, COLD_COLOR, 0.75 * (1.0 - heat))
+                    }
+                    None => rgb,
+                };
+                image.fill_rect(x * scale, y * scale, scale, scale, color);
+                if scale >= 6 {
+                    image.fill_rect(x * scale, y * scale, scale, 1, GRID_COLOR);
+                    image.fill_rect(x * scale, y * scale, 1, scale, GRID_COLOR);
+                }
+            }
+        }
+
+        let center = |(x, y): (usize, usize)| {
+            (
+                (x * scale + scale / 2) as f64,
+                (y * scale + scale / 2) as f64,
+            )
+        };
+        for new_move in self.moves.iter() {
+            let color = if new_move.slide {
+                SLIDE_COLOR
+            } else {
+                COMMAND_COLOR
+            };
+            let head_length = (scale / 3) as f64;
+            image.draw_arrow(
+                center(new_move.from),
+                center(new_move.to),
+                head_length,
+                color,
+                new_move.slide,
+            );
+        }
+        let mut toggles = self.toggles.iter().collect::<Vec<_>>();
+        toggles.sort();
+        for &position in toggles {
+            let (x, y) = center(position);
+            let arm = (scale / 4) as f64;
+            image.draw_line((x - arm, y - arm), (x + arm, y + arm), TOGGLE_COLOR, false);
+            image.draw_line((x - arm, y + arm), (x + arm, y - arm), TOGGLE_COLOR, false);
+        }
+        image
+    }
+}
+
+impl<W: Write, N: Number> Tracer<N> for TraceRenderer<W> {
+    fn trace(&mut self, step: &Step<N>) {
+        if step.old_position != step.new_position {
+            let (x, y) = step.new_position;
+            if let Some(index) = self.block_indices[y][x] {
+                self.block_visits[index] += 1;
+            }
+        }
+        match step.event {
+            Event::Command(_) => self.record_move(step.old_position, step.new_position, false),
+            Event::Slide => self.record_move(step.old_position, step.new_position, true),
+            Event::Toggle => {
+                self.record_move(step.old_position, step.new_position, true);
+                self.toggles.insert(step.new_position);
+            }
+            Event::Exit => (),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.writer.take() {
+            Some(writer) => self.render().write_png(writer),
+            None => Ok(()),
+        }
+    }
+}
+
+// how often a block was entered compared to the most visited block, on a logarithmic scale from 0 to 1
+fn heat(visits: u64, max_visits: u64) -> f64 {
+    if max_visits == 0 {
+        return 0.0;
+    }
+    (1.0 + visits as f64).ln() / (1.0 + max_visits as f64).ln()
+}
+
+fn blend(color: (u8, u8, u8), other: (u8, u8, u8), amount: f64) -> (u8, u8, u8) {
+    let mix = |a: u8, b: u8| (a as f64 * (1.0 - amount) + b as f64 * amount).round() as u8;
+    (
+        mix(color.0, other.0),
+        mix(color.1, other.1),
+        mix(color.2, other.2),
+    )
+}
+
+struct Image {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            data: vec![0xFF; width * height * 3],
+        }
+    }
+
+    fn set_pixel(&mut self, x: i64, y: i64, color: (u8, u8, u8)) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+        let offset = (y as usize * self.width + x as usize) * 3;
+        self.data[offset] = color.0;
+        self.data[offset + 1] = color.1;
+        self.data[offset + 2] = color.2;
+    }
+
+    fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: (u8, u8, u8)) {
+        for py in y..y + height {
+            for px in x..x + width {
+                self.set_pixel(px as i64, py as i64, color);
+            }
+        }
+    }
+
+    // Bresenham's line algorithm, dashed lines skip every other group of four pixels
+    fn draw_line(&mut self, from: (f64, f64), to: (f64, f64), color: (u8, u8, u8), dashed: bool) {
+        let (mut x, mut y) = (from.0.round() as i64, from.1.round() as i64);
+        let (x1, y1) = (to.0.round() as i64, to.1.round() as i64);
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let sx = if x < x1 { 1 } else { -1 };
+        let sy = if y < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let mut pixel_count = 0;
+        loop {
+            if !dashed || (pixel_count / 4) % 2 == 0 {
+                self.set_pixel(x, y, color);
+            }
+            pixel_count += 1;
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled_error = 2 * error;
+            if doubled_error >= dy {
+                error += dy;
+                x += sx;
+            }
+            if doubled_error <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+    }
+
+    fn draw_arrow(
+        &mut self,
+        from: (f64, f64),
+        to: (f64, f64),
+        head_length: f64,
+        color: (u8, u8, u8),
+        dashed: bool,
+    ) {
+        self.draw_line(from, to, color, dashed);
+        let length = ((to.0 - from.0).powi(2) + (to.1 - from.1).powi(2)).sqrt();
+        if length == 0.0 {
+            return;
+        }
+        let back = ((from.0 - to.0) / length, (from.1 - to.1) / length);
+        for angle in [0.5_f64, -0.5_f64].iter() {
+            let (sin, cos) = angle.sin_cos();
+            let head = (
+                to.0 + head_length * (back.0 * cos - back.1 * sin),
+                to.1 + head_length * (back.0 * sin + back.1 * cos),
+            );
+            self.draw_line(to, head, color, false);
+        }
+    }
+
+    fn write_png(&self, writer: impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use crate::command::Command;
+use crate::config::Compat;
+use crate::interpreter::Interpreter;
+use crate::number::Number;
+use crate::palette::PaletteColor;
+use crate::transition::{pointer_index, Transition, POINTER_STATES};
+
+/*
+ * A Location is a place the interpreter can be at between two steps: a color
+ * block, or a single white (or black) codel where the program starts or a slide
+ * through white got trapped (or stopped, with `Compat::Rpiet`).
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Location {
+    Block(usize),
+    Codel((usize, usize)),
+}
+
+/*
+ * The StateGraph lists every location the interpreter can reach from its start
+ * position, together with the transition it takes from there for each of the
+ * eight DP/CC combinations (in the order of `POINTER_STATES`).
+ * The start location always comes first.
+ */
+pub(crate) struct StateGraph {
+    pub locations: Vec<Location>,
+    pub edges: Vec<[Option<Edge>; 8]>,
+    pub compat: Compat,
+}
+
+impl Location {
+    // e.g. "block#12" or "codel (3, 4)" for white codels
+    pub fn name(&self) -> String {
+        match *self {
+            Location::Block(block_index) => format!("block#{}", block_index),
+            Location::Codel((x, y)) => format!("codel ({}, {})", x, y),
+        }
+    }
+
+    // e.g. "block#12 (dark red, 5 codels)" or "codel (3, 4)" for white codels
+    pub fn describe<N: Number>(&self, interpreter: &Interpreter<N>) -> String {
+        match *self {
+            Location::Block(block_index) => {
+                let block = &interpreter.blocks()[block_index];
+                let color = PaletteColor::Color {
+                    hue: block.hue,
+                    light: block.light,
+                };
+                let size = block.size();
+                let unit = if size == 1 { "codel" } else { "codels" };
+                format!("block#{} ({}, {} {})", block_index, color, size, unit)
+            }
+            Location::Codel((x, y)) => format!("codel ({}, {})", x, y),
+        }
+    }
+}
+
+/*
+ * A State is everything which decides the interpreter's next step: its location
+ * in the StateGraph, DP and CC (as an index into `POINTER_STATES`), and whether
+ * the next toggle after a blocked move rotates DP or switches CC.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct State {
+    pub location: usize,
+    pub pointer: usize,
+    pub toggle_dp_next: bool,
+}
+
+impl State {
+    // `POINTER_STATES` lists the directions clockwise, two CC values each
+    pub fn rotated(self, turns: usize) -> State {
+        State {
+            pointer: (self.pointer / 2 + turns) % 4 * 2 + self.pointer % 2,
+            ..self
+        }
+    }
+
+    pub fn switched(self) -> State {
+        State {
+            pointer: self.pointer ^ 1,
+            ..self
+        }
+    }
+
+    pub fn toggled(self) -> State {
+        let state = if self.toggle_dp_next {
+            self.rotated(1)
+        } else {
+            self.switched()
+        };
+        State {
+            toggle_dp_next: !self.toggle_dp_next,
+            ..state
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Edge {
+    pub target: usize,
+    pub transition: Transition,
+}
+
+impl StateGraph {
+    pub fn new<N: Number>(interpreter: &Interpreter<N>) -> StateGraph {
+        let location_at = |position: (usize, usize)| match interpreter.block_index_at(position) {
+            Some(block_index) => Location::Block(block_index),
+            None => Location::Codel(position),
+        };
+        let position_of = |location: Location| match location {
+            Location::Block(block_index) => interpreter.blocks()[block_index].codel_coordinates[0],
+            Location::Codel(position) => position,
+        };
+
+        let mut locations = vec![location_at(interpreter.current_position())];
+        let mut indices: HashMap<Location, usize> = HashMap::new();
+        indices.insert(locations[0], 0);
+        let mut edges = Vec::new();
+        while edges.len() < locations.len() {
+            let position = position_of(locations[edges.len()]);
+            let mut location_edges = [None; 8];
+            for (index, &(dp, cc)) in POINTER_STATES.iter().enumerate() {
+                let transition = match interpreter.find_transition(position, dp, cc) {
+                    Some(transition) => transition,
+                    None => continue,
+                };
+                let location = location_at(transition.new_position);
+                let target = *indices.entry(location).or_insert_with(|| {
+                    locations.push(location);
+                    locations.len() - 1
+                });
+                location_edges[index] = Some(Edge { target, transition });
+            }
+            edges.push(location_edges);
+        }
+        StateGraph {
+            locations,
+            edges,
+            compat: interpreter.compat(),
+        }
+    }
+    // the state of an interpreter which did not take a step yet
+    pub fn start_state<N: Number>(&self, interpreter: &Interpreter<N>) -> State {
+        State {
+            location: 0,
+            pointer: pointer_index(interpreter.dp(), interpreter.cc()),
+            toggle_dp_next: false,
+        }
+    }
+
+    // a location which cannot be left in any direction ends the program after eight toggles
+    pub fn is_terminal(&self, location: usize) -> bool {
+        self.edges[location].iter().all(Option::is_none)
+    }
+
+    // every state the interpreter may be in after one step from `state`, which are
+    // several after POINTER and SWITCH as their effect depends on the stack
+    pub fn next_states(&self, state: State) -> Vec<State> {
+        if self.is_terminal(state.location) {
+            return Vec::new();
+        }
+        let edge = match self.edges[state.location][state.pointer] {
+            Some(edge) => edge,
+            None => return vec![state.toggled()],
+        };
+        if !edge.transition.reached_new_block && self.compat.slides_turn_in_white() {
+            // trapped in a white area
+            return Vec::new();
+        }
+        let moved = State {
+            location: edge.target,
+            pointer: pointer_index(edge.transition.dp, edge.transition.cc),
+            // the next blocked move starts over with toggling CC, see `Compat`
+            toggle_dp_next: state.toggle_dp_next && !self.compat.restarts_toggling_after_move(),
+        };
+        if !edge.transition.reached_new_block {
+            // stopped on a white codel, which toggles like a blocked move
+            return vec![moved.toggled()];
+        }
+        match edge.transition.command {
+            Some(Command::Pointer) => (0..4).map(|turns| moved.rotated(turns)).collect(),
+            Some(Command::Switch) => vec![moved, moved.switched()],
+            _ => vec![moved],
+        }
+    }
+
+    // the successors of every state which can be reached from `start`
+    pub fn reachable_states(&self, start: State) -> HashMap<State, Vec<State>> {
+        let mut successors = HashMap::new();
+        let mut pending = vec![start];
+        while let Some(state) = pending.pop() {
+            if successors.contains_key(&state) {
+                continue;
+            }
+            let next_states = self.next_states(state);
+            pending.extend(next_states.iter().copied());
+            successors.insert(state, next_states);
+        }
+        successors
+    }
+}
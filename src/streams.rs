@@ -0,0 +1,100 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::str;
+
+/*
+ * Streams bundles the input and output handles a running Piet program reads from
+ * (IN_CHAR, IN_NUMBER) and writes to (OUT_CHAR, OUT_NUMBER).
+ * Every interpreter owns its own streams, so several interpreters can run in one
+ * process without sharing the real stdio.
+ */
+pub struct Streams<'a> {
+    pub input: Box<dyn BufRead + 'a>,
+    pub output: Box<dyn Write + 'a>,
+}
+
+impl<'a> Streams<'a> {
+    pub fn new(input: impl BufRead + 'a, output: impl Write + 'a) -> Streams<'a> {
+        Streams {
+            input: Box::new(input),
+            output: Box::new(output),
+        }
+    }
+
+    pub fn stdio() -> Streams<'static> {
+        Streams::new(BufReader::new(io::stdin()), io::stdout())
+    }
+
+    /// Reads the text of a number the way IN_NUMBER expects it: leading whitespace,
+    /// an optional sign and all digits which follow. Everything after the last digit
+    /// stays in the input for the next IN_CHAR or IN_NUMBER.
+    ///
+    /// Returns `None` if no digit follows (e.g. at the end of the input).
+    pub fn read_number_text(&mut self) -> io::Result<Option<String>> {
+        while let Some(byte) = self.peek_byte()? {
+            if !byte.is_ascii_whitespace() {
+                break;
+            }
+            self.input.consume(1);
+        }
+        let mut text = String::new();
+        if let Some(sign @ b'+') | Some(sign @ b'-') = self.peek_byte()? {
+            text.push(sign as char);
+            self.input.consume(1);
+        }
+        let mut has_digits = false;
+        while let Some(digit) = self.peek_byte()? {
+            if !digit.is_ascii_digit() {
+                break;
+            }
+            has_digits = true;
+            text.push(digit as char);
+            self.input.consume(1);
+        }
+        Ok(if has_digits { Some(text) } else { None })
+    }
+
+    /// Reads one UTF-8 encoded character for IN_CHAR. Invalid or truncated sequences
+    /// are consumed and read as U+FFFD (the replacement character).
+    pub fn read_char(&mut self) -> io::Result<Option<char>> {
+        let first = match self.read_byte()? {
+            Some(first) => first,
+            None => return Ok(None),
+        };
+        let length = match first {
+            0x00..=0x7F => 1,
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+        };
+        let mut bytes = vec![first];
+        while bytes.len() < length {
+            match self.peek_byte()? {
+                Some(byte) if byte & 0xC0 == 0x80 => {
+                    bytes.push(byte);
+                    self.input.consume(1);
+                }
+                _ => break,
+            }
+        }
+        Ok(Some(
+            str::from_utf8(&bytes)
+                .ok()
+                .and_then(|text| text.chars().next())
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        ))
+    }
+
+    /// Reads a single byte, for IN_CHAR in raw byte mode.
+    pub fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.peek_byte()?;
+        if byte.is_some() {
+            self.input.consume(1);
+        }
+        Ok(byte)
+    }
+
+    fn peek_byte(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.fill_buf()?.first().cloned())
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::command::Command;
+use crate::counters::{CodelChooser, DirectionPointer};
+use crate::number::Number;
+
+/*
+ * What happened during a single interpreter step.
+ * `Slide` means the interpreter reached a new block by sliding through white codels
+ * (which never executes a command), `Toggle` means it could not leave its current
+ * position and toggled CC or DP instead. `Exit` ends the program, also when a slide
+ * through white went around in circles.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Command(Command),
+    Slide,
+    Toggle,
+    Exit,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Command(command) => write!(f, "{}", command),
+            Event::Slide => write!(f, "slide"),
+            Event::Toggle => write!(f, "toggle"),
+            Event::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+/// A record of one `Interpreter::advance` call, handed to every registered [`Tracer`].
+///
+/// `dp`, `cc` and `stack` describe the state after the step, `block_size` is the size of the
+/// block the interpreter left (`None` when it was not in a color block).
+#[derive(Debug)]
+pub struct Step<'s, N: Number = i64> {
+    pub step: u128,
+    pub old_position: (usize, usize),
+    pub new_position: (usize, usize),
+    pub dp: DirectionPointer,
+    pub cc: CodelChooser,
+    pub event: Event,
+    pub block_size: Option<usize>,
+    pub stack: &'s [N],
+}
+
+/// A hook which gets called once per interpreter step.
+pub trait Tracer<N: Number = i64> {
+    fn trace(&mut self, step: &Step<N>);
+
+    /// Called once the traced run is over, reports errors which happened while tracing.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes every step as one JSON object per line.
+pub struct JsonLinesTracer<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(writer: W) -> JsonLinesTracer<W> {
+        JsonLinesTracer {
+            writer,
+            error: None,
+        }
+    }
+
+    fn write_step<N: Number>(&mut self, step: &Step<N>) -> io::Result<()> {
+        write!(
+            self.writer,
+            "{{\"step\":{},\"old_position\":[{},{}],\"new_position\":[{},{}],\"dp\":\"{}\",\"cc\":\"{}\",",
+            step.step,
+            step.old_position.0,
+            step.old_position.1,
+            step.new_position.0,
+            step.new_position.1,
+            step.dp,
+            step.cc
+        )?;
+        match step.event {
+            Event::Command(command) => write!(
+                self.writer,
+                "\"event\":\"command\",\"command\":\"{}\",",
+                command
+            )?,
+            event => write!(self.writer, "\"event\":\"{}\",", event)?,
+        }
+        match step.block_size {
+            Some(block_size) => write!(self.writer, "\"block_size\":{},", block_size)?,
+            None => write!(self.writer, "\"block_size\":null,")?,
+        }
+        let stack = step
+            .stack
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(self.writer, "\"stack\":[{}]}}", stack)
+    }
+}
+
+impl<W: Write, N: Number> Tracer<N> for JsonLinesTracer<W> {
+    fn trace(&mut self, step: &Step<N>) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.write_step(step) {
+            self.error = Some(e);
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.writer.flush()
+    }
+}
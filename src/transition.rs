@@ -0,0 +1,83 @@
+use crate::command::Command;
+use crate::counters::{CodelChooser, DirectionPointer};
+
+/*
+ * A Transition is where the interpreter goes when it leaves a codel with a given
+ * DP and CC: the codel it arrives at, DP and CC after the move (slides through
+ * white turn them at every obstacle), whether it slid through white codels on
+ * the way, whether it reached a color block or got trapped in a white area
+ * (which ends the program, or with `Compat::Rpiet` stopped on a white codel in
+ * front of an obstacle, where the interpreter toggles CC or DP) and the command
+ * leaving the block executes (if any).
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Transition {
+    pub new_position: (usize, usize),
+    pub dp: DirectionPointer,
+    pub cc: CodelChooser,
+    pub traveled_through_white: bool,
+    pub reached_new_block: bool,
+    pub command: Option<Command>,
+}
+
+/*
+ * A TransitionTable caches the transition of every color block for all eight
+ * combinations of DP and CC. `None` entries mean the block cannot be left in
+ * that direction and the interpreter needs to toggle its pointers.
+ */
+#[derive(Debug)]
+pub(crate) struct TransitionTable {
+    transitions: Vec<[Option<Transition>; 8]>,
+}
+
+impl TransitionTable {
+    pub fn new(
+        block_count: usize,
+        mut transition_for: impl FnMut(usize, DirectionPointer, CodelChooser) -> Option<Transition>,
+    ) -> TransitionTable {
+        let transitions = (0..block_count)
+            .map(|block_index| {
+                let mut transitions = [None; 8];
+                for (index, &(dp, cc)) in POINTER_STATES.iter().enumerate() {
+                    transitions[index] = transition_for(block_index, dp, cc);
+                }
+                transitions
+            })
+            .collect();
+        TransitionTable { transitions }
+    }
+
+    pub fn get(
+        &self,
+        block_index: usize,
+        dp: DirectionPointer,
+        cc: CodelChooser,
+    ) -> Option<Transition> {
+        self.transitions[block_index][pointer_index(dp, cc)]
+    }
+}
+
+// all combinations of DP and CC, `pointer_index` returns the position of a combination in this list
+pub(crate) const POINTER_STATES: [(DirectionPointer, CodelChooser); 8] = [
+    (DirectionPointer::Up, CodelChooser::Left),
+    (DirectionPointer::Up, CodelChooser::Right),
+    (DirectionPointer::Right, CodelChooser::Left),
+    (DirectionPointer::Right, CodelChooser::Right),
+    (DirectionPointer::Down, CodelChooser::Left),
+    (DirectionPointer::Down, CodelChooser::Right),
+    (DirectionPointer::Left, CodelChooser::Left),
+    (DirectionPointer::Left, CodelChooser::Right),
+];
+
+pub(crate) fn pointer_index(dp: DirectionPointer, cc: CodelChooser) -> usize {
+    let dp_index = match dp {
+        DirectionPointer::Up => 0,
+        DirectionPointer::Right => 1,
+        DirectionPointer::Down => 2,
+        DirectionPointer::Left => 3,
+    };
+    match cc {
+        CodelChooser::Left => dp_index * 2,
+        CodelChooser::Right => dp_index * 2 + 1,
+    }
+}
@@ -1,120 +1,196 @@
+use std::collections::HashMap;
 use std::fs::File;
-use std::process;
+use std::io::{self, Write};
 
 use gif::SetParameter;
 use png::ColorType::{Grayscale, GrayscaleAlpha, RGB, RGBA};
 
-use crate::cmd_options::CmdOptions;
+use crate::config::{CodelSize, Config};
+use crate::error::RpietError;
 
-pub struct OutputInfo {
-    pub width: u32,
-    pub height: u32,
+/// Rows of RGB codels, as produced by [`create_canvas`] and consumed by `Interpreter::from_rgb_rows`.
+pub type RgbRows = Vec<Vec<(u8, u8, u8)>>;
+
+struct OutputInfo {
+    width: u32,
 }
 
-pub fn create_canvas(file: &File, options: &CmdOptions) -> Vec<Vec<(u8, u8, u8)>> {
-    let (bytes, info) = parse_file(file, options);
+/// Decodes the PNG or GIF image in `file` into rows of RGB codels.
+///
+/// The image format is chosen by the extension of `file_path`. Every codel is
+/// sampled from the top-left pixel of its `config.codel_size` sized square.
+/// With `CodelSize::Auto` the codel size is detected from the image (see [`detect_codel_size`]).
+pub fn create_canvas(file: &File, file_path: &str, config: &Config) -> Result<RgbRows, RpietError> {
+    let (bytes, info) = parse_file(file, file_path, config)?;
     let pixels = group_pixels(bytes);
-    let canvas = reduce_to_codels_and_group_into_rows(pixels, options.codel_size, info.width);
-    if options.verbose {
+    let codel_size = match config.codel_size {
+        CodelSize::Fixed(codel_size) => codel_size,
+        CodelSize::Auto => {
+            let codel_size = detect_codel_size(&pixels, info.width as usize);
+            if config.verbose {
+                eprintln!("Detected a codel size of {}", codel_size);
+            }
+            codel_size
+        }
+    };
+    let canvas = reduce_to_codels_and_group_into_rows(pixels, codel_size, info.width);
+    if canvas.is_empty() || canvas[0].is_empty() {
+        return Err(RpietError::EmptyImage);
+    }
+    if config.verbose {
         eprintln!(
             "Creating canvas with {} codels per row and {} rows",
             canvas[0].len(),
             canvas.len()
         );
     }
-    canvas
+    Ok(canvas)
+}
+
+/// Encodes rows of RGB codels as a PNG image, drawing every codel as a square of `codel_size` pixels.
+pub fn write_png(
+    canvas: &[Vec<(u8, u8, u8)>],
+    codel_size: u32,
+    writer: impl Write,
+) -> io::Result<()> {
+    let codel_size = codel_size as usize;
+    let height = canvas.len() * codel_size;
+    let width = canvas.first().map_or(0, Vec::len) * codel_size;
+    let mut data = Vec::with_capacity(width * height * 3);
+    for row in canvas.iter() {
+        for _ in 0..codel_size {
+            for &(r, g, b) in row.iter() {
+                for _ in 0..codel_size {
+                    data.extend_from_slice(&[r, g, b]);
+                }
+            }
+        }
+    }
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    Ok(())
 }
 
-fn parse_file(file: &File, options: &CmdOptions) -> (Vec<u8>, OutputInfo) {
-    if options.file_path.ends_with(".png") {
-        return parse_png_file(file, options);
-    } else if options.file_path.ends_with(".gif") {
-        return parse_gif_file(file, options);
+fn parse_file(
+    file: &File,
+    file_path: &str,
+    config: &Config,
+) -> Result<(Vec<u8>, OutputInfo), RpietError> {
+    if file_path.ends_with(".png") {
+        parse_png_file(file, config)
+    } else if file_path.ends_with(".gif") {
+        parse_gif_file(file, config)
     } else {
-        eprintln!("Unknown filetype: {}", options.file_path);
-        process::exit(1);
+        Err(RpietError::UnsupportedFormat(file_path.to_string()))
     }
 }
 
-fn parse_gif_file(file: &File, options: &CmdOptions) -> (Vec<u8>, OutputInfo) {
+fn parse_gif_file(file: &File, config: &Config) -> Result<(Vec<u8>, OutputInfo), RpietError> {
     let mut decoder = gif::Decoder::new(file);
     decoder.set(gif::ColorOutput::RGBA);
-    let mut reader = match decoder.read_info() {
-        Ok(reader) => reader,
-        Err(e) => {
-            eprintln!("Application error: {}", e);
-            process::exit(1);
-        }
+    let mut reader = decoder.read_info()?;
+    let width = u32::from(reader.width());
+    let height = u32::from(reader.height());
+    check_codel_size(config, width, height)?;
+    // areas the first frame leaves uncovered show the background color, white without one
+    let background = match (reader.global_palette(), reader.bg_color()) {
+        (Some(palette), Some(index)) => [
+            palette[index * 3],
+            palette[index * 3 + 1],
+            palette[index * 3 + 2],
+        ],
+        _ => [0xFF, 0xFF, 0xFF],
     };
-    match reader.next_frame_info() {
-        Ok(Some(_)) => (),
-        Ok(None) => {
-            eprintln!("Application error: No frame data while reading gif");
-            process::exit(1);
-        }
-        Err(e) => {
-            eprintln!("Application error: {}", e);
-            process::exit(1);
-        }
+    let frame = match reader.read_next_frame()? {
+        Some(frame) => frame,
+        None => return Err(RpietError::EmptyImage),
     };
-    let mut data = vec![0; reader.buffer_size()];
-    match reader.read_into_buffer(&mut data) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Application error: {}", e);
-            process::exit(1);
-        }
-    };
-    if options.verbose {
+    let data = composite_frame(frame, width as usize, height as usize, background);
+    if config.verbose {
         eprintln!(
             "Parsed the file as valid GIF (width={}, height={})",
-            reader.width(),
-            reader.height()
+            width, height
         );
+        if (frame.left, frame.top) != (0, 0)
+            || (u32::from(frame.width), u32::from(frame.height)) != (width, height)
+        {
+            eprintln!(
+                "Placed the first frame ({}x{}) at ({}, {}) on the logical screen",
+                frame.width, frame.height, frame.left, frame.top
+            );
+        }
     }
-    let output_info = OutputInfo {
-        width: reader.width() as u32,
-        height: reader.height() as u32,
-    };
-    (convert_to_rgb(data, png::ColorType::RGBA), output_info)
+    Ok((data, OutputInfo { width }))
 }
 
-fn parse_png_file(file: &File, options: &CmdOptions) -> (Vec<u8>, OutputInfo) {
-    let decoder = png::Decoder::new(file);
-    let (info, mut reader) = match decoder.read_info() {
-        Ok(decoded) => decoded,
-        Err(e) => {
-            eprintln!("Application error: {}", e);
-            process::exit(1);
+// draws the RGBA pixels of a frame onto a screen filled with the background color,
+// skipping transparent pixels and everything outside of the screen
+fn composite_frame(
+    frame: &gif::Frame,
+    width: usize,
+    height: usize,
+    background: [u8; 3],
+) -> Vec<u8> {
+    let mut screen = background.repeat(width * height);
+    let frame_width = usize::from(frame.width);
+    if frame_width == 0 {
+        return screen;
+    }
+    let (left, top) = (usize::from(frame.left), usize::from(frame.top));
+    for (y, row) in frame.buffer.chunks_exact(frame_width * 4).enumerate() {
+        if top + y >= height {
+            break;
+        }
+        for (x, rgba) in row.chunks_exact(4).enumerate() {
+            if left + x >= width {
+                break;
+            }
+            if rgba[3] != 0 {
+                let offset = ((top + y) * width + left + x) * 3;
+                screen[offset..offset + 3].copy_from_slice(&rgba[..3]);
+            }
         }
-    };
-    if info.width % options.codel_size != 0 || info.height % options.codel_size != 0 {
-        eprintln!(
-            "Application error: codel_size {} does not fit into image dimensions ({}, {})",
-            options.codel_size, info.width, info.height
-        );
-        process::exit(1);
     }
+    screen
+}
+
+fn parse_png_file(file: &File, config: &Config) -> Result<(Vec<u8>, OutputInfo), RpietError> {
+    let decoder = png::Decoder::new(file);
+    let (info, mut reader) = decoder.read_info()?;
+    check_codel_size(config, info.width, info.height)?;
     let mut data = vec![0; info.buffer_size()];
-    reader.next_frame(&mut data).unwrap_or_else(|e| {
-        eprintln!("Application error: {}", e);
-        process::exit(1);
-    });
-    if options.verbose {
+    reader.next_frame(&mut data)?;
+    if config.verbose {
         eprintln!(
             "Parsed the file as valid PNG (width={}, height={})",
             info.width, info.height
         );
     }
-    let output_info = OutputInfo {
-        width: info.width,
-        height: info.height,
-    };
-    (convert_to_rgb(data, info.color_type), output_info)
+    let output_info = OutputInfo { width: info.width };
+    Ok((convert_to_rgb(data, info.color_type)?, output_info))
 }
 
-fn convert_to_rgb(img_data: Vec<u8>, color_type: png::ColorType) -> Vec<u8> {
-    match color_type {
+// a fixed codel size has to divide both image dimensions
+// (`is_multiple_of` would need Rust 1.87)
+#[allow(clippy::manual_is_multiple_of)]
+fn check_codel_size(config: &Config, width: u32, height: u32) -> Result<(), RpietError> {
+    if let CodelSize::Fixed(codel_size) = config.codel_size {
+        if codel_size == 0 || width % codel_size != 0 || height % codel_size != 0 {
+            return Err(RpietError::CodelSizeMismatch {
+                codel_size,
+                width,
+                height,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn convert_to_rgb(img_data: Vec<u8>, color_type: png::ColorType) -> Result<Vec<u8>, RpietError> {
+    let rgb = match color_type {
         RGB => img_data,
         RGBA => {
             let mut vec = Vec::with_capacity(img_data.len() / 4 * 3);
@@ -134,15 +210,105 @@ fn convert_to_rgb(img_data: Vec<u8>, color_type: png::ColorType) -> Vec<u8> {
             vec
         }
         GrayscaleAlpha => {
-            let mut vec = Vec::with_capacity(img_data.len() * 3);
+            let mut vec = Vec::with_capacity(img_data.len() / 2 * 3);
             for ga in img_data.chunks(2) {
                 let g = ga[0];
-                let a = ga[1];
-                vec.extend([g, g, g, a].iter().cloned())
+                vec.extend([g, g, g].iter().cloned())
             }
             vec
         }
-        _ => unreachable!("uncovered color type"),
+        color_type => {
+            return Err(RpietError::UnsupportedColorType(format!(
+                "{:?}",
+                color_type
+            )))
+        }
+    };
+    Ok(rgb)
+}
+
+/// Finds the largest codel size consistent with the given pixels.
+///
+/// Every horizontal and vertical run of equally colored pixels must consist of
+/// whole codels, so the codel size is the greatest common divisor of all run lengths.
+/// Images without any color change are ambiguous and get a codel size of 1.
+pub fn detect_codel_size(pixels: &[(u8, u8, u8)], width: usize) -> u32 {
+    if width == 0 || pixels.is_empty() {
+        return 1;
+    }
+    let height = pixels.len() / width;
+    let mut codel_size = 0;
+    let mut color_changed = false;
+    for y in 0..height {
+        let row = (0..width).map(|x| pixels[y * width + x]);
+        let (gcd, changed) = gcd_of_run_lengths(row, codel_size);
+        codel_size = gcd;
+        color_changed |= changed;
+    }
+    for x in 0..width {
+        let column = (0..height).map(|y| pixels[y * width + x]);
+        let (gcd, changed) = gcd_of_run_lengths(column, codel_size);
+        codel_size = gcd;
+        color_changed |= changed;
+        if codel_size == 1 {
+            break;
+        }
+    }
+    if !color_changed {
+        return 1;
+    }
+    codel_size as u32
+}
+
+// the codel size most rows and columns with a color change agree on, which is larger
+// than `detect_codel_size` when a few stray pixels break an otherwise regular grid
+pub(crate) fn most_common_codel_size(pixels: &[(u8, u8, u8)], width: usize) -> u32 {
+    if width == 0 || pixels.is_empty() {
+        return 1;
+    }
+    let height = pixels.len() / width;
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    let mut count_line = |line: Vec<(u8, u8, u8)>| {
+        if let (line_size, true) = gcd_of_run_lengths(line.into_iter(), 0) {
+            *counts.entry(line_size).or_insert(0) += 1;
+        }
+    };
+    for y in 0..height {
+        count_line((0..width).map(|x| pixels[y * width + x]).collect());
+    }
+    for x in 0..width {
+        count_line((0..height).map(|y| pixels[y * width + x]).collect());
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(line_size, count)| (count, line_size))
+        .map_or(1, |(line_size, _)| line_size as u32)
+}
+
+fn gcd_of_run_lengths(
+    line: impl Iterator<Item = (u8, u8, u8)>,
+    mut gcd_so_far: usize,
+) -> (usize, bool) {
+    let mut previous = None;
+    let mut run_length = 0;
+    let mut color_changed = false;
+    for pixel in line {
+        if previous.is_some() && previous != Some(pixel) {
+            gcd_so_far = gcd(gcd_so_far, run_length);
+            run_length = 0;
+            color_changed = true;
+        }
+        previous = Some(pixel);
+        run_length += 1;
+    }
+    (gcd(gcd_so_far, run_length), color_changed)
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
     }
 }
 
@@ -165,6 +331,6 @@ fn reduce_to_codels_and_group_into_rows(
     codels
         .chunks_exact((width / codel_size) as usize)
         .step_by(codel_size as usize)
-        .map(|row| Vec::from(row))
+        .map(Vec::from)
         .collect::<Vec<_>>()
 }
@@ -0,0 +1,264 @@
+mod common;
+
+use rpiet::{assemble, CodelSize, Config, RpietError};
+
+use common::TempFile;
+
+fn assemble_and_run(source: &str, input: &str) -> String {
+    common::run(assemble(source).unwrap(), input).0
+}
+
+fn assembly_error(source: &str) -> (usize, String) {
+    match assemble(source) {
+        Err(RpietError::InvalidAssembly { line, message }) => (line, message),
+        other => panic!("expected an assembly error, got {:?}", other),
+    }
+}
+
+#[test]
+fn prints_characters() {
+    let source = "
+        push 'H'
+        outc
+        push 'i'
+        outc
+        push '!'
+        outc
+        push '\\n'
+        outc
+    ";
+    assert_eq!(assemble_and_run(source, ""), "Hi!\n");
+}
+
+#[test]
+fn pushes_small_large_zero_and_negative_numbers() {
+    let mut source = String::new();
+    let values: [i64; 12] = [1, 2, 5, 16, 17, 100, 1000, 65536, 0, -1, -42, 123456789012];
+    for value in values.iter() {
+        source.push_str(&format!("push {}\noutn\npush ' '\noutc\n", value));
+    }
+    let expected = values
+        .iter()
+        .map(|value| format!("{} ", value))
+        .collect::<String>();
+    assert_eq!(assemble_and_run(&source, ""), expected);
+}
+
+#[test]
+fn runs_arithmetic_and_stack_commands() {
+    let source = "
+        push 7
+        push 3
+        add     ; 10
+        push 4
+        sub     ; 6
+        push 6
+        mul     ; 36
+        push 5
+        div     ; 7
+        dup
+        outn    ; prints 7
+        push 4
+        mod     ; 3
+        outn
+        push 2
+        push 1
+        gt
+        outn    ; 2 > 1
+        push 0
+        not
+        outn
+        push 9
+        pop
+        push 1
+        push 2
+        push 3
+        push 3
+        push 1
+        roll    ; 3 1 2, the 2 is printed first
+        outn
+        outn
+        outn
+    ";
+    assert_eq!(assemble_and_run(source, ""), "7311213");
+}
+
+#[test]
+fn reads_input() {
+    let source = "
+        inn
+        inc
+        inc
+        outc
+        pop
+        push 2
+        mul
+        outn
+    ";
+    assert_eq!(assemble_and_run(source, "21 x"), "x42");
+}
+
+#[test]
+fn loops_backwards() {
+    let source = "
+            push 5
+    loop:   dup
+            outn
+            push 1
+            sub
+            dup
+            jnz loop
+            pop
+    ";
+    assert_eq!(assemble_and_run(source, ""), "54321");
+}
+
+#[test]
+fn jumps_forwards_and_skips_code() {
+    let source = "
+            inn
+            jz zero
+            push 'n'
+            outc
+            jmp end
+    zero:   push 'z'
+            outc
+    end:    push '.'
+            outc
+    ";
+    assert_eq!(assemble_and_run(source, "0"), "z.");
+    assert_eq!(assemble_and_run(source, "3"), "n.");
+    assert_eq!(assemble_and_run(source, "-3"), "n.");
+}
+
+#[test]
+fn jumps_between_neighboring_labels() {
+    let source = "
+            jmp start
+    back:   jmp end         ; a jump right after a label
+    start:  push 1          ; a label right after a jump
+            outn
+            jmp back
+    end:    push 2
+            outn
+    ";
+    assert_eq!(assemble_and_run(source, ""), "12");
+
+    let source = "
+            inn
+    skip:   jz over
+    over:   push 7
+            outn
+    ";
+    assert_eq!(assemble_and_run(source, "0"), "7");
+    assert_eq!(assemble_and_run(source, "1"), "7");
+}
+
+#[test]
+fn jumps_into_shared_labels() {
+    let source = "
+            push 2          ; a counter
+    top:
+    again:  dup
+            jz next
+    next:   dup
+            outn
+            dup
+            jz done
+            push 1
+            sub
+            dup
+            jnz again
+            jmp top         ; a second jump into the same junction
+    done:   halt
+            push 8          ; never runs
+            outn
+    ";
+    assert_eq!(assemble_and_run(source, ""), "210");
+}
+
+#[test]
+fn computes_the_greatest_common_divisor() {
+    // Euclid's algorithm: gcd(a, b) = gcd(b, a mod b)
+    let source = "
+            inn
+            inn
+    loop:   dup
+            jz done
+            dup         ; a b b
+            push 3
+            push 1
+            roll        ; b a b
+            mod         ; b (a mod b)
+            jmp loop
+    done:   pop
+            outn
+    ";
+    assert_eq!(assemble_and_run(source, "12 18"), "6");
+    assert_eq!(assemble_and_run(source, "1071 462"), "21");
+    assert_eq!(assemble_and_run(source, "17 5"), "1");
+}
+
+#[test]
+fn ends_without_instructions() {
+    assert_eq!(assemble_and_run("", ""), "");
+    assert_eq!(assemble_and_run("# only a comment\nhalt\nhalt", ""), "");
+}
+
+#[test]
+fn round_trips_through_a_png_file() {
+    let source = "
+            push 3
+    loop:   push '*'
+            outc
+            push 1
+            sub
+            dup
+            jnz loop
+    ";
+    let canvas = assemble(source).unwrap();
+    let file = TempFile::new("assemble.png");
+    rpiet::write_png(&canvas, 3, file.create()).unwrap();
+    let config = Config {
+        codel_size: CodelSize::Auto,
+        ..Config::default()
+    };
+    let loaded = file.load_canvas(&config).unwrap();
+    assert_eq!(loaded, canvas);
+    assert_eq!(common::run(loaded, "").0, "***");
+}
+
+#[test]
+fn reports_mistakes_with_their_line() {
+    assert_eq!(
+        assembly_error("push 1\nfrobnicate"),
+        (2, String::from("unknown instruction `frobnicate`"))
+    );
+    assert_eq!(
+        assembly_error("push"),
+        (1, String::from("`push` expects a number or a character"))
+    );
+    assert_eq!(
+        assembly_error("push x"),
+        (1, String::from("invalid value `x`"))
+    );
+    assert_eq!(
+        assembly_error("dup 2"),
+        (1, String::from("`dup` does not take an argument, got `2`"))
+    );
+    assert_eq!(
+        assembly_error("a:\nb:\na: pop"),
+        (3, String::from("label `a` is defined twice"))
+    );
+    assert_eq!(
+        assembly_error("push 1\n\njnz nowhere"),
+        (3, String::from("unknown label `nowhere`"))
+    );
+    assert_eq!(
+        assembly_error("switch"),
+        (
+            1,
+            String::from("`switch` is used for jumps, use `jmp`, `jz` or `jnz` instead")
+        )
+    );
+}
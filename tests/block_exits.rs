@@ -0,0 +1,202 @@
+use rpiet::{BlockExit, CodelChooser, Config, DirectionPointer, Interpreter};
+
+const POINTERS: [(DirectionPointer, CodelChooser); 8] = [
+    (DirectionPointer::Up, CodelChooser::Left),
+    (DirectionPointer::Up, CodelChooser::Right),
+    (DirectionPointer::Right, CodelChooser::Left),
+    (DirectionPointer::Right, CodelChooser::Right),
+    (DirectionPointer::Down, CodelChooser::Left),
+    (DirectionPointer::Down, CodelChooser::Right),
+    (DirectionPointer::Left, CodelChooser::Left),
+    (DirectionPointer::Left, CodelChooser::Right),
+];
+
+// irregular blocks, `#` marks a codel of the block
+const SHAPES: [(&str, &str); 10] = [
+    ("square", "##\n##"),
+    ("L", "#..\n#..\n#..\n###"),
+    ("mirrored L", "..#\n..#\n###"),
+    ("U", "#...#\n#...#\n#####"),
+    ("upside down U", "#####\n#...#\n#...#"),
+    ("ring", "#####\n#...#\n#...#\n#####"),
+    ("plus", ".#.\n###\n.#."),
+    ("staircase", "#...\n##..\n.##.\n..##"),
+    ("comb", "#.#.#\n#####\n.#.#."),
+    ("spiral", "######\n#....#\n#.##.#\n#.#..#\n#.####"),
+];
+
+/// The exit rule spelled out for every DP/CC combination like the Piet specification
+/// lists it: the furthest edge in DP direction, then the extreme codel along that edge.
+fn reference_exit(
+    coords: &[(usize, usize)],
+    dp: DirectionPointer,
+    cc: CodelChooser,
+) -> (usize, usize) {
+    let edge: Vec<(usize, usize)> = match dp {
+        DirectionPointer::Up => {
+            let top = coords.iter().map(|c| c.1).min().unwrap();
+            coords.iter().cloned().filter(|c| c.1 == top).collect()
+        }
+        DirectionPointer::Right => {
+            let right = coords.iter().map(|c| c.0).max().unwrap();
+            coords.iter().cloned().filter(|c| c.0 == right).collect()
+        }
+        DirectionPointer::Down => {
+            let bottom = coords.iter().map(|c| c.1).max().unwrap();
+            coords.iter().cloned().filter(|c| c.1 == bottom).collect()
+        }
+        DirectionPointer::Left => {
+            let left = coords.iter().map(|c| c.0).min().unwrap();
+            coords.iter().cloned().filter(|c| c.0 == left).collect()
+        }
+    };
+    let edge = edge.into_iter();
+    match (dp, cc) {
+        // facing up, CC left is the image's left
+        (DirectionPointer::Up, CodelChooser::Left) => edge.min_by_key(|c| c.0),
+        (DirectionPointer::Up, CodelChooser::Right) => edge.max_by_key(|c| c.0),
+        // facing right, CC left is the image's top
+        (DirectionPointer::Right, CodelChooser::Left) => edge.min_by_key(|c| c.1),
+        (DirectionPointer::Right, CodelChooser::Right) => edge.max_by_key(|c| c.1),
+        // facing down, CC left is the image's right
+        (DirectionPointer::Down, CodelChooser::Left) => edge.max_by_key(|c| c.0),
+        (DirectionPointer::Down, CodelChooser::Right) => edge.min_by_key(|c| c.0),
+        // facing left, CC left is the image's bottom
+        (DirectionPointer::Left, CodelChooser::Left) => edge.max_by_key(|c| c.1),
+        (DirectionPointer::Left, CodelChooser::Right) => edge.min_by_key(|c| c.1),
+    }
+    .unwrap()
+}
+
+fn parse_shape(shape: &str, offset: (usize, usize)) -> Vec<(usize, usize)> {
+    shape
+        .lines()
+        .enumerate()
+        .flat_map(|(y, line)| {
+            line.chars()
+                .enumerate()
+                .filter(|&(_, c)| c == '#')
+                .map(move |(x, _)| (x + offset.0, y + offset.1))
+        })
+        .collect()
+}
+
+fn assert_exits(name: &str, coords: &[(usize, usize)]) {
+    let block_exit = BlockExit::from_coords(coords);
+    for &(dp, cc) in POINTERS.iter() {
+        assert_eq!(
+            block_exit.exit(dp, cc),
+            reference_exit(coords, dp, cc),
+            "exit of {} with dp={}, cc={}",
+            name,
+            dp,
+            cc
+        );
+    }
+}
+
+// a small linear congruential generator, good enough to shape random blocks reproducibly
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (self.0 >> 33) as usize % bound
+    }
+}
+
+// grows a connected block codel by codel from a random start inside a `size`x`size` area
+fn random_block(random: &mut Random, size: usize) -> Vec<(usize, usize)> {
+    let mut coords = vec![(random.below(size), random.below(size))];
+    let target = 1 + random.below(size * size / 2);
+    while coords.len() < target {
+        let (x, y) = coords[random.below(coords.len())];
+        let neighbor = match random.below(4) {
+            0 if y > 0 => (x, y - 1),
+            1 if x + 1 < size => (x + 1, y),
+            2 if y + 1 < size => (x, y + 1),
+            3 if x > 0 => (x - 1, y),
+            _ => continue,
+        };
+        if !coords.contains(&neighbor) {
+            coords.push(neighbor);
+        }
+    }
+    coords
+}
+
+#[test]
+fn finds_the_exits_of_irregular_blocks() {
+    for &(name, shape) in SHAPES.iter() {
+        assert_exits(name, &parse_shape(shape, (0, 0)));
+        assert_exits(name, &parse_shape(shape, (7, 3)));
+    }
+}
+
+#[test]
+fn finds_the_exits_of_single_codels_and_lines() {
+    assert_exits("single codel", &[(4, 2)]);
+    assert_exits("row", &parse_shape("#####", (1, 1)));
+    assert_exits("column", &parse_shape("#\n#\n#", (0, 5)));
+}
+
+#[test]
+fn exits_do_not_depend_on_the_order_of_codels() {
+    let mut random = Random(7);
+    for &(name, shape) in SHAPES.iter() {
+        let mut coords = parse_shape(shape, (2, 2));
+        let exits = BlockExit::from_coords(&coords);
+        for _ in 0..10 {
+            for i in (1..coords.len()).rev() {
+                coords.swap(i, random.below(i + 1));
+            }
+            let shuffled_exits = BlockExit::from_coords(&coords);
+            for &(dp, cc) in POINTERS.iter() {
+                assert_eq!(exits.exit(dp, cc), shuffled_exits.exit(dp, cc), "{}", name);
+            }
+        }
+    }
+}
+
+#[test]
+fn finds_the_exits_of_random_blocks() {
+    let mut random = Random(2021);
+    for round in 0..500 {
+        let coords = random_block(&mut random, 2 + round % 12);
+        assert_exits(&format!("random block {:?}", coords), &coords);
+    }
+}
+
+#[test]
+fn leaves_detected_blocks_through_their_exits() {
+    let red = (0xFF, 0x00, 0x00);
+    let white = (0xFF, 0xFF, 0xFF);
+    for &(name, shape) in SHAPES.iter() {
+        // the shape on a white frame, so the block does not touch the image edges
+        let coords = parse_shape(shape, (1, 1));
+        let width = coords.iter().map(|c| c.0).max().unwrap() + 2;
+        let height = coords.iter().map(|c| c.1).max().unwrap() + 2;
+        let mut canvas = vec![vec![white; width]; height];
+        for &(x, y) in coords.iter() {
+            canvas[y][x] = red;
+        }
+        let interpreter =
+            Interpreter::from_rgb_rows_with_io(canvas, &Config::default(), &b""[..], Vec::new())
+                .unwrap();
+        let block = &interpreter.blocks()[0];
+        assert_eq!(block.size(), coords.len(), "{}", name);
+        for &(dp, cc) in POINTERS.iter() {
+            assert_eq!(
+                block.exit_coordinates(&dp, &cc),
+                Some(reference_exit(&coords, dp, cc)),
+                "exit of {} with dp={}, cc={}",
+                name,
+                dp,
+                cc
+            );
+        }
+    }
+}
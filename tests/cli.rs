@@ -0,0 +1,149 @@
+mod common;
+
+use std::process::{Command, Output};
+
+use rpiet::{canvas_from_grid, write_png, RgbRows};
+
+use common::{TempFile, SQUARE_TO_2_POW_64};
+
+// runs the rpiet binary on a canvas saved as PNG
+fn rpiet(name: &str, canvas: &RgbRows, args: &[&str]) -> Output {
+    let file = TempFile::new(name);
+    write_png(canvas, 1, file.create()).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_rpiet"))
+        .args(args)
+        .arg(file.path())
+        .output()
+        .unwrap()
+}
+
+fn square_to_2_pow_64() -> RgbRows {
+    canvas_from_grid(SQUARE_TO_2_POW_64).unwrap()
+}
+
+#[test]
+fn verbose_runs_report_which_overflow_policy_handled_an_overflow() {
+    let reports = [
+        (
+            "ignore",
+            "skip executing MULTIPLY(4294967296, 4294967296) due to an overflow (overflow policy: ignore)",
+        ),
+        (
+            "wrap",
+            "execute MULTIPLY(4294967296, 4294967296) = 0 after an overflow (overflow policy: wrap)",
+        ),
+        (
+            "saturate",
+            "execute MULTIPLY(4294967296, 4294967296) = 9223372036854775807 after an overflow (overflow policy: saturate)",
+        ),
+    ];
+    for &(policy, report) in reports.iter() {
+        let output = rpiet(
+            &format!("overflow-{}.png", policy),
+            &square_to_2_pow_64(),
+            &["--verbose", "--overflow", policy],
+        );
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(report), "{}:\n{}", policy, stderr);
+        assert_eq!(output.status.code(), Some(0), "{}", policy);
+    }
+}
+
+#[test]
+fn the_halt_policy_exits_with_code_7() {
+    let output = rpiet(
+        "overflow-halt.png",
+        &square_to_2_pow_64(),
+        &["--verbose", "--overflow", "halt"],
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "skip executing MULTIPLY(4294967296, 4294967296) due to an overflow (overflow policy: halt)"
+        ),
+        "{}",
+        stderr
+    );
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn unknown_colors_are_summarized_in_one_warning() {
+    // twelve shades of gray, the last one twice
+    let mut canvas = canvas_from_grid("R  W  W  W  W  W  W  W  W  W  W  W  W  W").unwrap();
+    for x in 1..canvas[0].len() {
+        let gray = 0x10 * x.min(12) as u8;
+        canvas[0][x] = (gray, gray, gray);
+    }
+    // the program slides back and forth forever, only its setup matters
+    let output = rpiet("unknown-colors.png", &canvas, &["--max-steps", "10"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Warning: 13 codels have 12 unknown colors, they are read as white (see --unknown-color):",
+        "{}",
+        stderr
+    );
+    assert_eq!(lines[1], "  #101010: 1 codel, first at (1, 0)");
+    assert_eq!(lines[10], "  #A0A0A0: 1 codel, first at (10, 0)");
+    assert_eq!(lines[11], "  and 2 more colors");
+    assert_eq!(lines.len(), 12, "{}", stderr);
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = rpiet(
+        "unknown-colors-error.png",
+        &canvas,
+        &["--unknown-color", "error"],
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Application error: unknown codel color #101010 at position (1, 0)\n"
+    );
+    assert_eq!(output.status.code(), Some(8));
+
+    canvas[0].truncate(2);
+    let output = rpiet("unknown-color.png", &canvas, &["--max-steps", "10"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.lines().collect::<Vec<_>>(),
+        [
+            "Warning: 1 codel has 1 unknown color, it is read as white (see --unknown-color):",
+            "  #101010: 1 codel, first at (1, 0)",
+        ]
+    );
+}
+
+#[test]
+fn compile_rejects_options_the_generated_program_cannot_follow() {
+    let canvas = canvas_from_grid("R  dR").unwrap();
+    let rejected: [&[&str]; 5] = [
+        &["--overflow", "wrap"],
+        &["--numbers", "wrapping"],
+        &["--line-based-in-number"],
+        &["--raw-byte-in-char"],
+        &["--max-steps", "10"],
+    ];
+    for options in rejected.iter() {
+        let args = [&["compile"], *options].concat();
+        let output = rpiet("compile-rejects.png", &canvas, &args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains(&format!("{} is not supported by compile", options[0])),
+            "{:?}:\n{}",
+            options,
+            stderr
+        );
+        assert!(output.stdout.is_empty(), "{:?}", options);
+        assert_eq!(output.status.code(), Some(1), "{:?}", options);
+    }
+    let output = rpiet(
+        "compile-accepts.png",
+        &canvas,
+        &["compile", "--compat", "npiet"],
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("int main(void)"));
+}
@@ -0,0 +1,110 @@
+// helpers shared by the integration tests, each test file includes them with `mod common;`
+// and uses only some of them
+#![allow(dead_code)]
+
+pub mod samples;
+
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+use rpiet::{canvas_from_grid, create_canvas, Config, Interpreter, Number, RgbRows, RpietError};
+
+// push 2 and square it six times, the last multiplication (2^32 * 2^32) overflows i64
+pub const SQUARE_TO_2_POW_64: &str = "R  R  dR dB M  C  lB lG dC dY G  R  lY lM dR W  W
+                                      K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  W  W";
+
+// generous enough for every test program which ends on its own
+pub const MAX_STEPS: u128 = 1_000_000;
+
+pub fn limited_config() -> Config {
+    Config {
+        max_steps: MAX_STEPS,
+        unlimited_steps: false,
+        ..Config::default()
+    }
+}
+
+// runs a canvas like the binary does until the program ends,
+// returns what it printed and the stack it left behind
+pub fn run(canvas: RgbRows, input: impl AsRef<[u8]>) -> (String, Vec<i64>) {
+    run_with(canvas, &limited_config(), input)
+}
+
+pub fn run_grid(grid: &str, input: impl AsRef<[u8]>) -> (String, Vec<i64>) {
+    run(canvas_from_grid(grid).unwrap(), input)
+}
+
+pub fn run_with<N: Number>(
+    canvas: RgbRows,
+    config: &Config,
+    input: impl AsRef<[u8]>,
+) -> (String, Vec<N>) {
+    let mut output = Vec::new();
+    let mut interpreter =
+        Interpreter::<N>::new(canvas, config, input.as_ref(), &mut output).unwrap();
+    while interpreter.is_alive() {
+        interpreter.advance();
+    }
+    assert!(
+        config.unlimited_steps || interpreter.step_counter() < config.max_steps,
+        "the program did not end within {} steps",
+        config.max_steps
+    );
+    let stack = interpreter.stack().to_vec();
+    drop(interpreter);
+    (String::from_utf8(output).unwrap(), stack)
+}
+
+// an interpreter for a grid without input or output, to inspect it step by step
+pub fn grid_interpreter(grid: &str, config: &Config) -> Interpreter<'static> {
+    Interpreter::from_rgb_rows_with_io(
+        canvas_from_grid(grid).unwrap(),
+        config,
+        io::empty(),
+        io::sink(),
+    )
+    .unwrap()
+}
+
+// whether a program can be run, tests which need an external tool skip themselves without it
+pub fn is_installed(program: &str, args: &[&str]) -> bool {
+    Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+// a file in the temp directory, unique to the test process and removed when dropped
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn new(name: &str) -> TempFile {
+        TempFile(env::temp_dir().join(format!("rpiet-{}-{}", process::id(), name)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn create(&self) -> File {
+        File::create(&self.0).unwrap()
+    }
+
+    // loads the file like the binary does, its extension picks the image format
+    pub fn load_canvas(&self, config: &Config) -> Result<RgbRows, RpietError> {
+        let file = File::open(&self.0).unwrap();
+        create_canvas(&file, self.0.to_str().unwrap(), config)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
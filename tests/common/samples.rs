@@ -0,0 +1,172 @@
+// the images in `sample_images/` with the options to run them and their expected
+// output in `tests/conformance/`, see `tests/conformance.rs`
+use std::fs::{self, File};
+use std::path::Path;
+
+use rpiet::{create_canvas, CodelSize, Config, Palette, RgbRows};
+
+use super::MAX_STEPS;
+
+pub struct Sample {
+    pub image: &'static str,
+    pub codel_size: u32,
+    pub input: &'static str,
+    // a palette file in `tests/conformance/` for images drawn with other shades
+    pub palette: Option<&'static str>,
+    // programs which never end only compare what they print in the first steps,
+    // and are not compiled
+    pub max_steps: Option<u128>,
+}
+
+pub const SAMPLES: [Sample; 15] = [
+    // prints "99bottles" without a space: the path which prints a number goes from OUT_NUMBER at
+    // (17, 26) straight to the column which prints "bottles" at (72, 6), and that one starts with
+    // 'b'; the spaces in the rest of the verse are part of the other columns
+    Sample {
+        image: "99bottles.png",
+        codel_size: 1,
+        input: "",
+        palette: None,
+        max_steps: None,
+    },
+    Sample {
+        image: "GameOfLife.png",
+        codel_size: 1,
+        input: "",
+        palette: None,
+        max_steps: Some(20_000),
+    },
+    // the leading `\r` bytes are on purpose: for every character it reads, the loop in the
+    // first row computes 1 + 12 with DUPLICATE and ADD and prints it as a carriage return
+    Sample {
+        image: "cowsay.png",
+        codel_size: 1,
+        input: "Moo!\n",
+        palette: None,
+        max_steps: None,
+    },
+    Sample {
+        image: "euclid_clint.png",
+        codel_size: 1,
+        input: "42\n56\n",
+        palette: None,
+        max_steps: None,
+    },
+    // stops after 16, which is what the image says: its loop bound is computed from the sizes of
+    // the blocks in its first row as 1 * 11 + 5, under every `Compat` preset and in rpiet <= 0.3
+    Sample {
+        image: "fizzbuzz.png",
+        codel_size: 1,
+        input: "",
+        palette: None,
+        max_steps: None,
+    },
+    Sample {
+        image: "hello_world_c1.png",
+        codel_size: 1,
+        input: "",
+        palette: Some("hello_world.palette"),
+        max_steps: None,
+    },
+    Sample {
+        image: "hello_world_c4.png",
+        codel_size: 4,
+        input: "",
+        palette: Some("hello_world.palette"),
+        max_steps: None,
+    },
+    Sample {
+        image: "hello_world_globe.png",
+        codel_size: 1,
+        input: "",
+        palette: None,
+        max_steps: None,
+    },
+    Sample {
+        image: "helloworld-mondrian.png",
+        codel_size: 1,
+        input: "",
+        palette: None,
+        max_steps: None,
+    },
+    Sample {
+        image: "hw2-11.gif",
+        codel_size: 1,
+        input: "",
+        palette: None,
+        max_steps: Some(2_000),
+    },
+    Sample {
+        image: "hw5.png",
+        codel_size: 1,
+        input: "",
+        palette: None,
+        max_steps: Some(2_000),
+    },
+    // prints U+0001 over and over instead of a text: almost all of its blocks are single codels
+    // which push 1, and the path it takes ends in a loop of PUSH 1, OUT_CHAR and POP (`rpiet lint`
+    // reports it never ends); rpiet <= 0.3 and every `Compat` preset print the same
+    Sample {
+        image: "japh.png",
+        codel_size: 1,
+        input: "",
+        palette: None,
+        max_steps: Some(2_000),
+    },
+    Sample {
+        image: "piet_pi.png",
+        codel_size: 1,
+        input: "",
+        palette: None,
+        max_steps: None,
+    },
+    Sample {
+        image: "power2.png",
+        codel_size: 1,
+        input: "2\n10\n",
+        palette: None,
+        max_steps: None,
+    },
+    // prints "13is\x1b\x1c!prime" under every `Compat` preset: for every input above 1 the POINTER at
+    // (0, 86) leads to DUPLICATE, PUSH 2, DUPLICATE, a slide to the light blue block at (59, 89)
+    // which can only be left downwards, and POP, POP, without a test on the way; the output for
+    // "not" at (0, 121) then adds 14, 15 and 20 to the input instead of 96. Inputs 0 and 1 print
+    // "isnotprime" like the image intends, the image has no spaces
+    Sample {
+        image: "primetest2.png",
+        codel_size: 1,
+        input: "13\n",
+        palette: None,
+        max_steps: None,
+    },
+];
+
+// configures a run like the binary with the sample's options
+pub fn config(sample: &Sample, precompute_transitions: bool) -> Config {
+    let palette = match sample.palette {
+        Some(file_name) => {
+            let path = Path::new("tests/conformance").join(file_name);
+            Palette::parse(&fs::read_to_string(path).unwrap()).unwrap()
+        }
+        None => Palette::standard(),
+    };
+    Config {
+        codel_size: CodelSize::Fixed(sample.codel_size),
+        max_steps: sample.max_steps.unwrap_or(MAX_STEPS),
+        unlimited_steps: false,
+        palette,
+        precompute_transitions,
+        ..Config::default()
+    }
+}
+
+pub fn load_canvas(sample: &Sample, config: &Config) -> RgbRows {
+    let path = Path::new("sample_images").join(sample.image);
+    let file = File::open(&path).unwrap();
+    create_canvas(&file, sample.image, config).unwrap()
+}
+
+pub fn expected_output_path(sample: &Sample) -> String {
+    let name = Path::new(sample.image).file_stem().unwrap();
+    format!("tests/conformance/{}.out", name.to_str().unwrap())
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use rpiet::{compile, Compat, CompileTarget, Config, Interpreter};
+
+use common::samples::{config, expected_output_path, load_canvas, SAMPLES};
+use common::TempFile;
+
+// builds the program and runs it with the given input
+fn build_and_run(
+    target: CompileTarget,
+    compiler: &[&str],
+    name: &str,
+    code: &str,
+    input: &str,
+) -> Output {
+    let extension = match target {
+        CompileTarget::C => "c",
+        CompileTarget::Rust => "rs",
+    };
+    let source = TempFile::new(&format!("compiled-{}.{}", name, extension));
+    let program = TempFile::new(&format!("compiled-{}-{}", name, extension));
+    fs::write(source.path(), code).unwrap();
+    let build = Command::new(compiler[0])
+        .args(&compiler[1..])
+        .arg("-o")
+        .arg(program.path())
+        .arg(source.path())
+        .output()
+        .unwrap();
+    assert!(
+        build.status.success(),
+        "{} does not build as {}:\n{}",
+        name,
+        target,
+        String::from_utf8_lossy(&build.stderr)
+    );
+
+    let mut child = Command::new(program.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/*
+ * Compiles every sample image which ends on its own to C and to Rust, builds the programs
+ * and compares what they print to `tests/conformance/<image name>.out`. Each test skips
+ * itself when its compiler is not installed.
+ *
+ * The programs are built without optimizations, which is quicker and lets the overflow
+ * checks of Rust's debug builds catch mistakes in the generated code.
+ */
+fn compiled_samples_print_their_expected_output(target: CompileTarget, compiler: &[&str]) {
+    if !common::is_installed(compiler[0], &["--version"]) {
+        eprintln!(
+            "{} is not installed, skipping the {} backend",
+            compiler[0], target
+        );
+        return;
+    }
+    for sample in SAMPLES.iter().filter(|sample| sample.max_steps.is_none()) {
+        let config = config(sample, false);
+        let interpreter = Interpreter::from_rgb_rows_with_io(
+            load_canvas(sample, &config),
+            &config,
+            std::io::empty(),
+            std::io::sink(),
+        )
+        .unwrap();
+        let output = build_and_run(
+            target,
+            compiler,
+            sample.image,
+            &compile(&interpreter, target, sample.image),
+            sample.input,
+        );
+        let expected = fs::read(expected_output_path(sample)).unwrap();
+        assert!(
+            output.status.success() && output.stdout == expected,
+            "{} compiled to {} printed\n{}\nbut expects\n{}",
+            sample.image,
+            target,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&expected)
+        );
+    }
+}
+
+#[test]
+fn compiled_c_programs_print_the_expected_output() {
+    compiled_samples_print_their_expected_output(CompileTarget::C, &["cc"]);
+}
+
+#[test]
+fn compiled_rust_programs_print_the_expected_output() {
+    // the file names are no valid crate names
+    compiled_samples_print_their_expected_output(
+        CompileTarget::Rust,
+        &["rustc", "--crate-name", "program"],
+    );
+}
+
+// push 5, push 1, push 4, subtract, mod, out number, then a block which cannot be left
+const MOD_WITH_A_NEGATIVE_DIVISOR: &str = "R  K  K  K  K  K  K  K  K  K  K  K  K  lG lG lG
+                                           R  R  R  R  dR lR lR lR lR R  dY lC G  lG lG lG
+                                           K  K  K  K  K  K  K  K  K  K  K  K  K  lG lG lG";
+
+fn compiled_programs_follow_the_compat_preset(target: CompileTarget, compiler: &[&str]) {
+    if !common::is_installed(compiler[0], &["--version"]) {
+        eprintln!(
+            "{} is not installed, skipping the {} backend",
+            compiler[0], target
+        );
+        return;
+    }
+    let presets = [
+        (Compat::Spec, "-1"),
+        (Compat::Npiet, "-1"),
+        (Compat::Rpiet, "2"),
+    ];
+    for &(compat, expected) in presets.iter() {
+        let config = Config {
+            compat,
+            ..Config::default()
+        };
+        let interpreter = common::grid_interpreter(MOD_WITH_A_NEGATIVE_DIVISOR, &config);
+        let name = format!("mod-{}", compat);
+        let code = compile(&interpreter, target, &name);
+        let output = build_and_run(target, compiler, &name, &code, "");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            expected,
+            "{} with {} compat",
+            target,
+            compat
+        );
+    }
+}
+
+#[test]
+fn compiled_c_programs_follow_the_compat_preset() {
+    compiled_programs_follow_the_compat_preset(CompileTarget::C, &["cc"]);
+}
+
+#[test]
+fn compiled_rust_programs_follow_the_compat_preset() {
+    compiled_programs_follow_the_compat_preset(
+        CompileTarget::Rust,
+        &["rustc", "--crate-name", "program"],
+    );
+}
@@ -0,0 +1,85 @@
+/*
+ * Runs every image in `sample_images/` and compares what it prints to the
+ * expected output checked in at `tests/conformance/<image name>.out`.
+ *
+ * After an intended change of behavior, rewrite the expected outputs with
+ *
+ *     RPIET_BLESS=1 cargo test --test conformance
+ *
+ * and review the diff before committing it.
+ */
+
+mod common;
+
+use std::fs;
+
+use rpiet::{Config, Interpreter};
+
+use common::samples::{config, expected_output_path, load_canvas, Sample, SAMPLES};
+
+// runs a sample like the binary does and returns what it printed
+fn run(sample: &Sample, config: &Config) -> Vec<u8> {
+    let canvas = load_canvas(sample, config);
+    let mut output = Vec::new();
+    let mut interpreter =
+        Interpreter::from_rgb_rows_with_io(canvas, config, sample.input.as_bytes(), &mut output)
+            .unwrap();
+    while interpreter.is_alive() {
+        interpreter.advance();
+    }
+    if sample.max_steps.is_none() {
+        assert!(
+            interpreter.step_counter() < config.max_steps,
+            "{} did not end within {} steps",
+            sample.image,
+            config.max_steps
+        );
+    }
+    assert!(interpreter.error().is_none(), "{}", sample.image);
+    drop(interpreter);
+    output
+}
+
+#[test]
+fn every_sample_image_has_a_conformance_case() {
+    let mut images: Vec<String> = fs::read_dir("sample_images")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    images.sort();
+    let listed: Vec<String> = SAMPLES.iter().map(|s| String::from(s.image)).collect();
+    assert_eq!(images, listed);
+}
+
+#[test]
+fn sample_images_print_their_expected_output() {
+    let bless = std::env::var_os("RPIET_BLESS").is_some();
+    for sample in SAMPLES.iter() {
+        let output = run(sample, &config(sample, false));
+        let path = expected_output_path(sample);
+        if bless {
+            fs::write(&path, &output).unwrap();
+            continue;
+        }
+        let expected = fs::read(&path).unwrap_or_else(|_| panic!("{} is missing", path));
+        assert!(
+            output == expected,
+            "{} printed\n{}\nbut {} expects\n{}",
+            sample.image,
+            String::from_utf8_lossy(&output),
+            path,
+            String::from_utf8_lossy(&expected)
+        );
+    }
+}
+
+#[test]
+fn precomputed_transitions_do_not_change_the_output() {
+    for sample in SAMPLES.iter() {
+        assert!(
+            run(sample, &config(sample, true)) == run(sample, &config(sample, false)),
+            "{}",
+            sample.image
+        );
+    }
+}
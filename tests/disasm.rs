@@ -0,0 +1,69 @@
+mod common;
+
+use rpiet::{disassemble, Config};
+
+use common::grid_interpreter;
+
+fn listing(grid: &str) -> String {
+    disassemble(&grid_interpreter(grid, &Config::default()), "test.png")
+}
+
+#[test]
+fn switch_branches_into_runs_which_end_in_a_trapped_slide() {
+    // push 1, SWITCH, then slide right into white which traps the program
+    let listing = listing(
+        "R  dR C  W  W
+         K  K  K  W  W",
+    );
+    assert_eq!(
+        listing,
+        "# test.png: 3 blocks, 4 reachable states, starting at L0
+
+L0:
+    block#0 (red, 1 codel) --dp=right,cc=left--> block#1: PUSH 1
+    block#1 (dark red, 1 codel) --dp=right,cc=left--> block#2: SWITCH
+    branch: dp=right,cc=left -> L1, dp=right,cc=right -> L2
+
+L1:
+    block#2 (cyan, 1 codel) --dp=right,cc=left--> codel (4, 0): trapped in white, the program ends
+
+L2:
+    block#2 (cyan, 1 codel) --dp=right,cc=right--> codel (4, 0): trapped in white, the program ends
+"
+    );
+}
+
+#[test]
+fn pointer_branches_in_every_direction() {
+    // push 1, POINTER
+    let listing = listing(
+        "R  dR lC W  W
+         K  K  K  W  W",
+    );
+    let branch = listing
+        .lines()
+        .find(|line| line.trim_start().starts_with("branch:"))
+        .unwrap();
+    for direction in &["dp=right", "dp=down", "dp=left", "dp=up"] {
+        assert!(branch.contains(direction), "{}", branch);
+    }
+    assert!(
+        listing.contains("block#1 (dark red, 1 codel) --dp=right,cc=left--> block#2: POINTER\n")
+    );
+}
+
+#[test]
+fn a_block_which_cannot_be_left_ends_the_program() {
+    let listing = listing(
+        "R  K
+         K  K",
+    );
+    assert_eq!(
+        listing,
+        "# test.png: 1 blocks, 1 reachable states, starting at L0
+
+L0:
+    block#0 (red, 1 codel) dp=right,cc=left: cannot move in any direction, the program ends
+"
+    );
+}
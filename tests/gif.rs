@@ -0,0 +1,110 @@
+mod common;
+
+use std::borrow::Cow;
+
+use rpiet::{canvas_from_grid, CodelSize, Config, RgbRows, RpietError};
+
+use common::TempFile;
+
+// white, red, dark blue and light green; the first entry is the background color
+const PALETTE: [u8; 12] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xC0, 0xC0, 0xFF, 0xC0,
+];
+
+// writes a GIF of the given screen size with a single frame and loads it like the binary does
+fn load_gif(
+    name: &str,
+    screen: (u16, u16),
+    palette: &[u8],
+    frame: gif::Frame,
+    codel_size: CodelSize,
+) -> Result<RgbRows, RpietError> {
+    let file = TempFile::new(&format!("{}.gif", name));
+    {
+        let mut encoder = gif::Encoder::new(file.create(), screen.0, screen.1, palette).unwrap();
+        encoder.write_frame(&frame).unwrap();
+    }
+    let config = Config {
+        codel_size,
+        ..Config::default()
+    };
+    file.load_canvas(&config)
+}
+
+#[test]
+fn places_a_smaller_first_frame_at_its_offset() {
+    let frame = gif::Frame {
+        left: 2,
+        top: 1,
+        width: 2,
+        height: 2,
+        buffer: Cow::Owned(vec![1, 2, 3, 1]),
+        ..gif::Frame::default()
+    };
+    let canvas = load_gif("offset", (5, 4), &PALETTE, frame, CodelSize::Fixed(1)).unwrap();
+    let expected = canvas_from_grid(
+        "W  W  W  W  W
+         W  W  R  dB W
+         W  W  lG R  W
+         W  W  W  W  W",
+    )
+    .unwrap();
+    assert_eq!(canvas, expected);
+}
+
+#[test]
+fn transparent_pixels_and_uncovered_areas_show_the_background_color() {
+    let mut palette = PALETTE.to_vec();
+    // black as background
+    palette[..3].copy_from_slice(&[0x00, 0x00, 0x00]);
+    let frame = gif::Frame {
+        width: 3,
+        height: 2,
+        transparent: Some(3),
+        buffer: Cow::Owned(vec![1, 3, 2, 3, 1, 3]),
+        ..gif::Frame::default()
+    };
+    let canvas = load_gif("transparent", (4, 2), &palette, frame, CodelSize::Fixed(1)).unwrap();
+    let expected = canvas_from_grid(
+        "R  K  dB K
+         K  R  K  K",
+    )
+    .unwrap();
+    assert_eq!(canvas, expected);
+}
+
+#[test]
+fn codel_size_has_to_fit_the_logical_screen() {
+    // the frame alone would fit codels of size 2, but the screen does not
+    let frame = gif::Frame {
+        width: 2,
+        height: 2,
+        buffer: Cow::Owned(vec![1, 1, 1, 1]),
+        ..gif::Frame::default()
+    };
+    let result = load_gif("codel-size", (4, 3), &PALETTE, frame, CodelSize::Fixed(2));
+    assert!(matches!(
+        result,
+        Err(RpietError::CodelSizeMismatch {
+            codel_size: 2,
+            width: 4,
+            height: 3
+        })
+    ));
+    let frame = gif::Frame {
+        left: 2,
+        width: 2,
+        height: 2,
+        buffer: Cow::Owned(vec![1, 1, 1, 1]),
+        ..gif::Frame::default()
+    };
+    let canvas = load_gif(
+        "codel-size-fits",
+        (4, 2),
+        &PALETTE,
+        frame,
+        CodelSize::Fixed(2),
+    )
+    .unwrap();
+    assert_eq!(canvas, canvas_from_grid("W R").unwrap());
+}
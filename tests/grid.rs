@@ -0,0 +1,78 @@
+mod common;
+
+use rpiet::{canvas_from_grid, write_png, CodelSize, Config, RpietError};
+
+use common::TempFile;
+
+const RED: (u8, u8, u8) = (0xFF, 0x00, 0x00);
+const LIGHT_RED: (u8, u8, u8) = (0xFF, 0xC0, 0xC0);
+const DARK_BLUE: (u8, u8, u8) = (0x00, 0x00, 0xC0);
+const WHITE: (u8, u8, u8) = (0xFF, 0xFF, 0xFF);
+const BLACK: (u8, u8, u8) = (0x00, 0x00, 0x00);
+
+fn grid_error(grid: &str) -> (usize, String) {
+    match canvas_from_grid(grid) {
+        Err(RpietError::InvalidGrid { line, message }) => (line, message),
+        other => panic!("expected a grid error, got {:?}", other),
+    }
+}
+
+#[test]
+fn reads_color_names() {
+    assert_eq!(
+        canvas_from_grid("lR R dB . W K").unwrap(),
+        vec![vec![LIGHT_RED, RED, DARK_BLUE, WHITE, WHITE, BLACK]]
+    );
+}
+
+#[test]
+fn reads_every_color_of_the_palette() {
+    let canvas = canvas_from_grid(
+        "
+        lR lY lG lC lB lM
+        R  Y  G  C  B  M
+        dR dY dG dC dB dM
+        ",
+    )
+    .unwrap();
+    let config = Config::default();
+    let mut colors: Vec<(u8, u8, u8)> = canvas.into_iter().flatten().collect();
+    colors.sort();
+    colors.dedup();
+    assert_eq!(colors.len(), 18);
+    assert!(colors
+        .iter()
+        .all(|&rgb| config.palette.lookup(rgb).is_some()));
+}
+
+#[test]
+fn rejects_unknown_colors_and_ragged_rows() {
+    assert_eq!(grid_error("R X"), (1, String::from("unknown color `X`")));
+    assert_eq!(
+        grid_error("R lRR"),
+        (1, String::from("unknown color `lRR`"))
+    );
+    assert_eq!(
+        grid_error("\nR R\nR"),
+        (
+            3,
+            String::from("the row has 1 codels, but the first row has 2")
+        )
+    );
+    assert!(matches!(
+        canvas_from_grid("\n  \n"),
+        Err(RpietError::EmptyImage)
+    ));
+}
+
+#[test]
+fn writes_grids_as_png() {
+    let canvas = canvas_from_grid("R  dR W\nK  lY .").unwrap();
+    let file = TempFile::new("grid.png");
+    write_png(&canvas, 4, file.create()).unwrap();
+    let config = Config {
+        codel_size: CodelSize::Fixed(4),
+        ..Config::default()
+    };
+    assert_eq!(file.load_canvas(&config).unwrap(), canvas);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use std::io;
+
+use rpiet::{canvas_from_grid, Config, Interpreter, RgbRows, RpietError, UnknownColor};
+
+#[test]
+fn interpreters_can_be_debug_printed() {
+    let mut interpreter =
+        common::grid_interpreter("R  R  dR W  W\nK  K  K  W  W", &Config::default());
+    interpreter.advance();
+    let debug = format!("{:?}", interpreter);
+    assert!(debug.starts_with("Interpreter {"), "{}", debug);
+    assert!(debug.contains("dp: Right"), "{}", debug);
+    assert!(debug.contains("stack: [2]"), "{}", debug);
+    assert!(debug.ends_with(", .. }"), "{}", debug);
+}
+
+// a shade of green between red and green, which is none of the Piet colors
+const OFF_GREEN: (u8, u8, u8) = (0x10, 0xF0, 0x10);
+
+// red, the off-palette green and green in a row, above a row of black
+fn canvas_with_off_green() -> RgbRows {
+    let mut canvas = canvas_from_grid("R  G  G\nK  K  K").unwrap();
+    canvas[0][1] = OFF_GREEN;
+    canvas
+}
+
+// the position after the first step, which depends on how the unknown color was read
+fn position_after_one_step(unknown_color: UnknownColor) -> (usize, usize) {
+    let config = Config {
+        unknown_color,
+        ..Config::default()
+    };
+    let mut interpreter = Interpreter::from_rgb_rows_with_io(
+        canvas_with_off_green(),
+        &config,
+        io::empty(),
+        io::sink(),
+    )
+    .unwrap();
+    interpreter.advance();
+    interpreter.current_position()
+}
+
+#[test]
+fn unknown_colors_are_read_as_configured() {
+    // a white codel is slid through to the green codel behind it
+    assert_eq!(position_after_one_step(UnknownColor::White), (2, 0));
+    // a black codel blocks the way, so the pointers are toggled in place
+    assert_eq!(position_after_one_step(UnknownColor::Black), (0, 0));
+    // the nearest color is green, which forms a block with the green codel
+    assert_eq!(position_after_one_step(UnknownColor::Nearest), (1, 0));
+    let config = Config {
+        unknown_color: UnknownColor::Error,
+        ..Config::default()
+    };
+    let result = Interpreter::from_rgb_rows_with_io(
+        canvas_with_off_green(),
+        &config,
+        io::empty(),
+        io::sink(),
+    );
+    assert!(matches!(
+        result,
+        Err(RpietError::UnknownColor {
+            color: OFF_GREEN,
+            position: (1, 0)
+        })
+    ));
+}
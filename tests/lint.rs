@@ -0,0 +1,117 @@
+mod common;
+
+use rpiet::{canvas_from_grid, lint, CodelSize, Compat, Config, LintIssue, LintKind};
+
+fn lint_grid(grid: &str, config: &Config) -> Vec<LintIssue> {
+    lint(canvas_from_grid(grid).unwrap(), config).unwrap()
+}
+
+fn kinds_and_positions(issues: &[LintIssue]) -> Vec<(LintKind, (usize, usize))> {
+    issues
+        .iter()
+        .map(|issue| (issue.kind, issue.position))
+        .collect()
+}
+
+#[test]
+fn a_program_without_mistakes_has_no_issues() {
+    let issues = lint_grid(
+        "R  R  R
+         R  R  R
+         K  K  K",
+        &Config::default(),
+    );
+    assert_eq!(issues, vec![]);
+}
+
+#[test]
+fn finds_blocks_which_are_never_entered() {
+    let issues = lint_grid(
+        "R  K  dB
+         K  K  K",
+        &Config::default(),
+    );
+    assert_eq!(
+        issues.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        vec!["2:0: unreachable-block: block#1 (dark blue, 1 codel) is never entered"]
+    );
+}
+
+#[test]
+fn finds_white_loops() {
+    let grid = "R  W  W
+                K  W  W";
+    // the slide goes around in circles and gets trapped, which ends the program
+    let issues = lint_grid(grid, &Config::default());
+    assert_eq!(
+        issues.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        vec!["2:0: white-loop: sliding through white goes around in circles and traps the program here, coming from block#0 (red, 1 codel) with dp=right,cc=left"]
+    );
+    // while the legacy slides keep going around forever
+    let config = Config {
+        compat: Compat::Rpiet,
+        ..Config::default()
+    };
+    let issues = lint_grid(grid, &config);
+    assert_eq!(
+        kinds_and_positions(&issues),
+        vec![(LintKind::WhiteLoop, (1, 0)), (LintKind::NeverEnds, (0, 0))]
+    );
+    assert_eq!(
+        issues[0].message,
+        "sliding through white gets stuck in a loop over 4 white codels and never ends"
+    );
+}
+
+#[test]
+fn finds_stray_codels() {
+    let mut pixels = canvas_from_grid(
+        "R  R  R
+         R  R  R
+         K  K  K",
+    )
+    .unwrap();
+    // a blend of red and white, as anti-aliasing leaves it
+    pixels[0][1] = (0xF0, 0x10, 0x10);
+    let issues = lint(pixels, &Config::default()).unwrap();
+    assert_eq!(
+        issues.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        vec![
+            "1:0: unknown-color: #F01010 is no color of the palette, its 1 codel is read as white",
+            "1:0: stray-codel: a single codel of #F01010, which is read as white, is likely left over from anti-aliasing",
+        ]
+    );
+}
+
+#[test]
+fn finds_codels_with_several_colors() {
+    let mut pixels = canvas_from_grid(
+        "R  R  dR dR
+         R  R  dR dR
+         K  K  K  K
+         K  K  K  K",
+    )
+    .unwrap();
+    pixels[1][3] = (0xFF, 0xFF, 0xFF);
+
+    let config = Config {
+        codel_size: CodelSize::Fixed(2),
+        ..Config::default()
+    };
+    let issues = lint(pixels.clone(), &config).unwrap();
+    assert_eq!(issues[0].kind, LintKind::CodelSize);
+    assert_eq!(issues[0].position, (1, 0));
+    assert_eq!(
+        issues[0].message,
+        "the 2x2 pixels of this codel have 2 colors, it is read as #C00000"
+    );
+
+    // the white pixel forces the detected codel size down to 1
+    let issues = lint(pixels, &Config::default()).unwrap();
+    assert_eq!(issues[0].kind, LintKind::CodelSize);
+    assert_eq!(issues[0].position, (2, 0));
+    assert_eq!(
+        issues[0].message,
+        "the 2x2 pixels at (2, 0) have 2 colors while most of the image uses codels of 2 pixels, so the codel size was detected as 1"
+    );
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::io::Write;
+use std::process::Command;
+
+use rpiet::{canvas_from_grid, Compat, Config, RgbRows};
+
+use common::TempFile;
+
+/*
+ * Runs tiny images through npiet and through rpiet with `Compat::Npiet` and compares
+ * what they print. The test skips itself when `npiet` is not on the PATH.
+ *
+ * Every image ends in a white square which traps the program after a slide that turns
+ * at each edge, the first rows of the `Compat` table.
+ */
+const PROGRAMS: [(&str, &str); 5] = [
+    (
+        "push 7, OUT_NUMBER",
+        "R  R  R  R  R  R  R  dR lM W  W
+         K  K  K  K  K  K  K  K  K  W  W",
+    ),
+    (
+        "push 3, push 1, push 1, SUBTRACT, DIVIDE, OUT_NUMBER, OUT_NUMBER",
+        "R  R  R  dR lR R  dY dC lG Y  W  W
+         K  K  K  K  K  K  K  K  K  K  W  W",
+    ),
+    (
+        "push 3, push 1, push 1, SUBTRACT, MOD, OUT_NUMBER, OUT_NUMBER",
+        "R  R  R  dR lR R  dY lC G  dY W  W
+         K  K  K  K  K  K  K  K  K  K  W  W",
+    ),
+    (
+        "push 1, push 2, SUBTRACT, push 1, ROLL, OUT_NUMBER, OUT_NUMBER",
+        "R  dR dR lR Y  dY lM B  dC W  W
+         K  K  K  K  K  K  K  K  K  W  W",
+    ),
+    (
+        "a slide trapped in white right away",
+        "R  W  W
+         K  W  W",
+    ),
+];
+
+// npiet always reads PPM images, PNG and GIF depend on how it was built
+fn write_ppm(canvas: &RgbRows, file: &TempFile) {
+    let mut ppm = format!("P6\n{} {}\n255\n", canvas[0].len(), canvas.len()).into_bytes();
+    for &(r, g, b) in canvas.iter().flatten() {
+        ppm.extend_from_slice(&[r, g, b]);
+    }
+    file.create().write_all(&ppm).unwrap();
+}
+
+#[test]
+fn npiet_compat_prints_what_npiet_prints() {
+    if !common::is_installed("npiet", &["-v"]) {
+        eprintln!("npiet is not installed, skipping the comparison");
+        return;
+    }
+    let config = Config {
+        compat: Compat::Npiet,
+        ..common::limited_config()
+    };
+    for (index, &(name, grid)) in PROGRAMS.iter().enumerate() {
+        let canvas = canvas_from_grid(grid).unwrap();
+        let file = TempFile::new(&format!("npiet-{}.ppm", index));
+        write_ppm(&canvas, &file);
+        let npiet = Command::new("npiet")
+            .args(["-q", "-cs", "1"])
+            .arg(file.path())
+            .output()
+            .unwrap();
+        let (output, _) = common::run_with::<i64>(canvas, &config, "");
+        assert_eq!(output, String::from_utf8_lossy(&npiet.stdout), "{}", name);
+    }
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use rpiet::{canvas_from_grid, detect_codel_size, CodelSize, Config, RgbRows};
+
+use common::samples::{load_canvas, SAMPLES};
+use common::TempFile;
+
+// writes a PNG with 8 bit channels of the given color type and loads it like the binary does
+fn load_png(
+    name: &str,
+    color_type: png::ColorType,
+    size: (u32, u32),
+    data: &[u8],
+) -> rpiet::RgbRows {
+    let file = TempFile::new(name);
+    {
+        let mut encoder = png::Encoder::new(file.create(), size.0, size.1);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(data)
+            .unwrap();
+    }
+    let config = Config {
+        codel_size: CodelSize::Fixed(1),
+        ..Config::default()
+    };
+    file.load_canvas(&config).unwrap()
+}
+
+#[test]
+fn reads_gray_pixels_and_drops_their_alpha() {
+    // white, black and a transparent white, then a transparent black and two opaque whites
+    let data = [
+        0xFF, 0xFF, 0x00, 0xFF, 0xFF, 0x00, //
+        0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF,
+    ];
+    let canvas = load_png(
+        "gray-alpha.png",
+        png::ColorType::GrayscaleAlpha,
+        (3, 2),
+        &data,
+    );
+    assert_eq!(canvas, canvas_from_grid("W K W\nK W W").unwrap());
+}
+
+#[test]
+fn reads_gray_pixels() {
+    let canvas = load_png(
+        "gray.png",
+        png::ColorType::Grayscale,
+        (3, 1),
+        &[0x00, 0xFF, 0x00],
+    );
+    assert_eq!(canvas, canvas_from_grid("K W K").unwrap());
+}
+
+// detects the codel size of `rows` as `create_canvas` does with `CodelSize::Auto`
+fn detect(rows: &RgbRows) -> u32 {
+    detect_codel_size(&rows.concat(), rows[0].len())
+}
+
+#[test]
+fn detects_the_codel_size_of_a_sample() {
+    let sample = SAMPLES
+        .iter()
+        .find(|sample| sample.image == "hello_world_c4.png")
+        .unwrap();
+    let config = Config {
+        codel_size: CodelSize::Fixed(1),
+        ..Config::default()
+    };
+    assert_eq!(detect(&load_canvas(sample, &config)), 4);
+}
+
+#[test]
+fn falls_back_to_a_codel_size_of_one_without_a_color_change() {
+    assert_eq!(detect(&canvas_from_grid("R R R R\nR R R R").unwrap()), 1);
+    assert_eq!(detect_codel_size(&[], 0), 1);
+}
+
+#[test]
+fn detects_the_largest_size_all_run_lengths_share() {
+    // runs of 4 and 6 pixels only share a factor of 2
+    let rows = canvas_from_grid(
+        "R R R R B B B B B B
+         R R R R B B B B B B",
+    )
+    .unwrap();
+    assert_eq!(detect(&rows), 2);
+    // one pixel wide runs rule out any larger size
+    let rows = canvas_from_grid(
+        "R R B B B B
+         R R B B B B
+         R G B B B B
+         R R B B B B",
+    )
+    .unwrap();
+    assert_eq!(detect(&rows), 1);
+}
@@ -0,0 +1,688 @@
+mod common;
+
+use std::num::Wrapping;
+
+use rpiet::{canvas_from_grid, ArithmeticPolicy, CodelChooser, Compat, Config, DirectionPointer};
+
+/*
+ * Tiny hand-built images (see `canvas_from_grid`) which pin down the semantics
+ * of single commands and rules. Most programs are one row of color blocks, where
+ * a block of N codels is N codels wide, followed by a white square which traps
+ * the interpreter:
+ *
+ *     R  R  R  dR dR lR lY W  W      push 3, push 2, add
+ *     K  K  K  K  K  K  K  W  W
+ */
+
+// runs the image until it ends, returns what it printed and the stack it left behind
+fn run(grid: &str, input: &str) -> (String, Vec<i64>) {
+    let config = Config {
+        max_steps: 1_000,
+        ..common::limited_config()
+    };
+    common::run_with(canvas_from_grid(grid).unwrap(), &config, input)
+}
+
+// takes the given number of steps and returns DP and CC afterwards
+fn pointers_after(grid: &str, steps: usize) -> (DirectionPointer, CodelChooser) {
+    let mut interpreter = common::grid_interpreter(grid, &Config::default());
+    for _ in 0..steps {
+        interpreter.advance();
+    }
+    (interpreter.dp(), interpreter.cc())
+}
+
+fn assert_stack(name: &str, grid: &str, expected: &[i64]) {
+    let (_, stack) = run(grid, "");
+    assert_eq!(stack, expected, "{}:\n{}", name, grid);
+}
+
+#[test]
+fn stack_commands() {
+    let programs: [(&str, &str, &[i64]); 9] = [
+        (
+            "push 3, push 2, pop",
+            "R  R  R  dR dR lR dR W  W
+             K  K  K  K  K  K  K  W  W",
+            &[3],
+        ),
+        (
+            "push 3, push 2, add",
+            "R  R  R  dR dR lR lY W  W
+             K  K  K  K  K  K  K  W  W",
+            &[5],
+        ),
+        (
+            "push 3, push 2, subtract",
+            "R  R  R  dR dR lR Y  W  W
+             K  K  K  K  K  K  K  W  W",
+            &[1],
+        ),
+        (
+            "push 3, push 2, multiply",
+            "R  R  R  dR dR lR dY W  W
+             K  K  K  K  K  K  K  W  W",
+            &[6],
+        ),
+        (
+            "push 7, push 3, divide",
+            "R  R  R  R  R  R  R  dR dR dR lR lG W  W
+             K  K  K  K  K  K  K  K  K  K  K  K  W  W",
+            &[2],
+        ),
+        (
+            "push 3, not, not",
+            "R  R  R  dR G  lB W  W
+             K  K  K  K  K  K  W  W",
+            &[1],
+        ),
+        (
+            "push 3, push 2, greater",
+            "R  R  R  dR dR lR lC W  W
+             K  K  K  K  K  K  K  W  W",
+            &[1],
+        ),
+        (
+            "push 2, push 3, greater",
+            "R  R  dR dR dR lR lC W  W
+             K  K  K  K  K  K  K  W  W",
+            &[0],
+        ),
+        (
+            "push 3, duplicate",
+            "R  R  R  dR dB W  W
+             K  K  K  K  K  W  W",
+            &[3, 3],
+        ),
+    ];
+    for (name, grid, expected) in programs.iter() {
+        assert_stack(name, grid, expected);
+    }
+}
+
+#[test]
+fn mod_takes_the_sign_of_the_divisor() {
+    assert_stack(
+        "push 2, push 3, mod",
+        "R  R  dR dR dR lR G  W  W
+         K  K  K  K  K  K  K  W  W",
+        &[2],
+    );
+    assert_stack(
+        "push 2, push 3, subtract, push 3, mod",
+        "R  R  dR dR dR lR Y  Y  Y  dY lC W  W
+         K  K  K  K  K  K  K  K  K  K  K  W  W",
+        &[2],
+    );
+    assert_stack(
+        "push 5, push 1, push 4, subtract, mod",
+        "R  R  R  R  R  dR lR lR lR lR R  dY lC W  W
+         K  K  K  K  K  K  K  K  K  K  K  K  K  W  W",
+        &[-1],
+    );
+    assert_stack(
+        "push 1, push 6, subtract, push 1, push 4, subtract, mod",
+        "R  dR dR dR dR dR dR lR Y  dY dY dY dY lY G  dB W  W
+         K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  W  W",
+        &[-2],
+    );
+}
+
+#[test]
+fn commands_on_a_short_stack_are_skipped() {
+    assert_stack(
+        "pop",
+        "R  lR W  W
+         K  K  W  W",
+        &[],
+    );
+    assert_stack(
+        "push 3, add",
+        "R  R  R  dR dY W  W
+         K  K  K  K  K  W  W",
+        &[3],
+    );
+}
+
+#[test]
+fn roll() {
+    let programs: [(&str, &str, &[i64]); 7] = [
+        (
+            "push 1, 2, 3, roll 1 to depth 3",
+            "R  dR dR lR lR lR R  R  R  dR lR B  W  W
+             K  K  K  K  K  K  K  K  K  K  K  K  W  W",
+            &[3, 1, 2],
+        ),
+        (
+            "push 1, 2, 3, roll 1 to depth 2",
+            "R  dR dR lR lR lR R  R  dR lR B  W  W
+             K  K  K  K  K  K  K  K  K  K  K  W  W",
+            &[1, 3, 2],
+        ),
+        (
+            "push 1, 2, 3, roll -1 to depth 3",
+            "R  dR dR lR lR lR R  R  R  dR lR lR R  dY lM W  W
+             K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  W  W",
+            &[2, 3, 1],
+        ),
+        (
+            "push 1, 2, 3, roll 4 to depth 3 rolls once",
+            "R  dR dR lR lR lR R  R  R  dR dR dR dR lR B  W  W
+             K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  W  W",
+            &[3, 1, 2],
+        ),
+        (
+            "push 1, 2, 0, roll 1 to depth 0 is skipped",
+            "R  dR dR lR R  dR lY Y  dM W  W
+             K  K  K  K  K  K  K  K  K  W  W",
+            &[1, 2, 0, 1],
+        ),
+        (
+            "push 1, 2, 5, roll 1 to depth 5 is skipped",
+            "R  dR dR lR lR lR lR lR R  dR lB W  W
+             K  K  K  K  K  K  K  K  K  K  K  W  W",
+            &[1, 2, 5, 1],
+        ),
+        (
+            "push 1, -1, roll 1 to depth -1 is skipped",
+            "R  dR lR lR R  dY lY M  W  W
+             K  K  K  K  K  K  K  K  W  W",
+            &[1, -1, 1],
+        ),
+    ];
+    for (name, grid, expected) in programs.iter() {
+        assert_stack(name, grid, expected);
+    }
+}
+
+#[test]
+fn input_and_output() {
+    assert_eq!(
+        run("R  lB W  W\nK  K  W  W", " 42\n"),
+        (String::new(), vec![42])
+    );
+    assert_eq!(
+        run("R  M  W  W\nK  K  W  W", "A"),
+        (String::new(), vec![65])
+    );
+    assert_eq!(
+        run("R  lB B  M  dB W  W\nK  K  K  K  K  W  W", "41\n"),
+        (String::from("42"), vec![])
+    );
+    assert_eq!(
+        run("R  M  lB lC dG W  W\nK  K  K  K  K  W  W", "Hi"),
+        (String::from("Hi"), vec![])
+    );
+}
+
+// IN_CHAR twice
+const TWO_CHARS: &str = "R  M  B  W  W
+                         K  K  K  W  W";
+
+fn read_two_chars(input: &[u8], raw_byte_in_char: bool) -> Vec<i64> {
+    let config = Config {
+        raw_byte_in_char,
+        ..common::limited_config()
+    };
+    common::run_with::<i64>(canvas_from_grid(TWO_CHARS).unwrap(), &config, input).1
+}
+
+#[test]
+fn in_char_decodes_utf8() {
+    assert_eq!(read_two_chars("é€".as_bytes(), false), [0xE9, 0x20AC]);
+    assert_eq!(read_two_chars("🦀a".as_bytes(), false), [0x1F980, 97]);
+    // invalid and truncated sequences are read as U+FFFD
+    assert_eq!(read_two_chars(b"\xFFa", false), [0xFFFD, 97]);
+    assert_eq!(read_two_chars(b"\xE2\x82a", false), [0xFFFD, 97]);
+    assert_eq!(read_two_chars(b"\xE2\x82", false), [0xFFFD]);
+    assert_eq!(read_two_chars(b"\xA9a", false), [0xFFFD, 97]);
+    // OUT_CHAR writes what IN_CHAR read
+    assert_eq!(
+        run("R  M  lB lC dG W  W\nK  K  K  K  K  W  W", "é€"),
+        (String::from("é€"), vec![])
+    );
+}
+
+#[test]
+fn raw_byte_in_char_reads_single_bytes() {
+    assert_eq!(read_two_chars("é".as_bytes(), true), [0xC3, 0xA9]);
+    assert_eq!(read_two_chars(b"\xFFa", true), [0xFF, 97]);
+}
+
+// IN_NUMBER, then IN_CHAR
+const NUMBER_THEN_CHAR: &str = "R  lB lC W  W
+                                K  K  K  W  W";
+
+#[test]
+fn in_number_leaves_what_follows_the_number_in_the_input() {
+    assert_eq!(run(NUMBER_THEN_CHAR, "-7\nZ").1, [-7, 10]);
+    assert_eq!(run(NUMBER_THEN_CHAR, "12Z").1, [12, 90]);
+    assert_eq!(run(NUMBER_THEN_CHAR, "+5 ").1, [5, 32]);
+    assert_eq!(run(NUMBER_THEN_CHAR, " \n\t-0x").1, [0, 120]);
+    // without digits IN_NUMBER is skipped, but a sign is consumed
+    assert_eq!(run(NUMBER_THEN_CHAR, "Z7").1, [90]);
+    assert_eq!(run(NUMBER_THEN_CHAR, "-Z").1, [90]);
+    assert_eq!(run(NUMBER_THEN_CHAR, "").1, []);
+}
+
+#[test]
+fn line_based_in_number_discards_the_rest_of_the_line() {
+    let config = Config {
+        line_based_in_number: true,
+        ..common::limited_config()
+    };
+    let run = |input: &str| {
+        common::run_with::<i64>(canvas_from_grid(NUMBER_THEN_CHAR).unwrap(), &config, input).1
+    };
+    assert_eq!(run("-7\nZ"), [-7, 90]);
+    assert_eq!(run(" +5 \nZ"), [5, 90]);
+    // the whole line has to be a number
+    assert_eq!(run("5 rest\nZ"), [90]);
+}
+
+#[test]
+fn pointer_turns_dp_clockwise() {
+    let programs = [
+        (
+            "push 1, pointer",
+            "R  dR lC W  W\nK  K  K  W  W",
+            2,
+            DirectionPointer::Down,
+        ),
+        (
+            "push 2, pointer",
+            "R  R  dR lC W  W\nK  K  K  K  W  W",
+            2,
+            DirectionPointer::Left,
+        ),
+        (
+            "push 3, pointer",
+            "R  R  R  dR lC W  W\nK  K  K  K  K  W  W",
+            2,
+            DirectionPointer::Up,
+        ),
+        (
+            "push 1, push 2, subtract, pointer",
+            "R  dR dR lR Y  dB W  W\nK  K  K  K  K  K  W  W",
+            4,
+            DirectionPointer::Up,
+        ),
+    ];
+    for &(name, grid, steps, dp) in programs.iter() {
+        assert_eq!(
+            pointers_after(grid, steps),
+            (dp, CodelChooser::Left),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn switch_toggles_cc_for_odd_values() {
+    assert_eq!(
+        pointers_after("R  dR C  W  W\nK  K  K  W  W", 2),
+        (DirectionPointer::Right, CodelChooser::Right)
+    );
+    assert_eq!(
+        pointers_after("R  R  dR C  W  W\nK  K  K  K  W  W", 2),
+        (DirectionPointer::Right, CodelChooser::Left)
+    );
+    // push 1, push 2, subtract, switch with -1
+    assert_eq!(
+        pointers_after("R  dR dR lR Y  lB W  W\nK  K  K  K  K  K  W  W", 4),
+        (DirectionPointer::Right, CodelChooser::Right)
+    );
+    // push 1, push 3, subtract, switch with -2
+    assert_eq!(
+        pointers_after("R  dR dR dR lR Y  lB W  W\nK  K  K  K  K  K  K  W  W", 4),
+        (DirectionPointer::Right, CodelChooser::Left)
+    );
+}
+
+#[test]
+fn sliding_through_white_executes_no_command() {
+    // without the white codel, red to dark red would push 3
+    assert_stack(
+        "slide, push 1",
+        "R  R  R  W  dR lR W  W
+         K  K  K  K  K  K  W  W",
+        &[1],
+    );
+}
+
+#[test]
+fn slides_turn_at_black_codels() {
+    // at the black codel CC is toggled and DP turns clockwise, down into green
+    let grid = "R  W  K
+                K  W  K
+                K  G  K";
+    let mut interpreter = common::grid_interpreter(grid, &Config::default());
+    interpreter.advance();
+    assert_eq!(interpreter.current_position(), (1, 2));
+    assert_eq!(
+        (interpreter.dp(), interpreter.cc()),
+        (DirectionPointer::Down, CodelChooser::Right)
+    );
+    assert!(interpreter.is_alive());
+}
+
+#[test]
+fn a_trapped_slide_ends_the_program() {
+    // the slide circles around the white square without reaching a color block
+    let grid = "R  W  W
+                K  W  W";
+    let mut interpreter = common::grid_interpreter(grid, &Config::default());
+    interpreter.advance();
+    assert!(!interpreter.is_alive());
+    assert_eq!(interpreter.step_counter(), 1);
+}
+
+#[test]
+fn eight_toggles_without_a_move_end_the_program() {
+    // CC and DP take turns, so the pointers come back to where they started
+    let toggles = [
+        (DirectionPointer::Right, CodelChooser::Right),
+        (DirectionPointer::Down, CodelChooser::Right),
+        (DirectionPointer::Down, CodelChooser::Left),
+        (DirectionPointer::Left, CodelChooser::Left),
+        (DirectionPointer::Left, CodelChooser::Right),
+        (DirectionPointer::Up, CodelChooser::Right),
+        (DirectionPointer::Up, CodelChooser::Left),
+        (DirectionPointer::Right, CodelChooser::Left),
+    ];
+    for grid in ["R", "R  R\nR  R", "G  K\nK  K"].iter() {
+        let mut interpreter = common::grid_interpreter(grid, &Config::default());
+        for &pointers in toggles.iter() {
+            assert!(interpreter.is_alive(), "{}", grid);
+            interpreter.advance();
+            assert_eq!((interpreter.dp(), interpreter.cc()), pointers, "{}", grid);
+        }
+        assert!(interpreter.is_alive(), "{}", grid);
+        interpreter.advance();
+        assert!(!interpreter.is_alive(), "{}", grid);
+        assert_eq!(interpreter.step_counter(), 9, "{}", grid);
+    }
+}
+
+#[test]
+fn toggling_starts_over_with_cc_after_a_move() {
+    // the top red codel is blocked, toggling CC leads from the bottom one to green,
+    // where the next blocked move toggles CC again instead of turning DP
+    let grid = "R  K
+                R  G";
+    assert_eq!(
+        pointers_after(grid, 1),
+        (DirectionPointer::Right, CodelChooser::Right)
+    );
+    assert_eq!(
+        pointers_after(grid, 2),
+        (DirectionPointer::Right, CodelChooser::Right)
+    );
+    assert_eq!(
+        pointers_after(grid, 3),
+        (DirectionPointer::Right, CodelChooser::Left)
+    );
+}
+
+// `common::SQUARE_TO_2_POW_64`, then print the top value
+const PRINT_2_POW_64: &str = "R  R  dR dB M  C  lB lG dC dY G  R  lY lM dR lM W  W
+                              K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  W  W";
+
+#[test]
+fn number_backends() {
+    let config = common::limited_config();
+    // i64 skips the last multiplication, which would overflow
+    let (output, stack) =
+        common::run_with::<i64>(canvas_from_grid(PRINT_2_POW_64).unwrap(), &config, "");
+    assert_eq!((output.as_str(), stack), ("4294967296", vec![1 << 32]));
+    let (output, stack) =
+        common::run_with::<Wrapping<i64>>(canvas_from_grid(PRINT_2_POW_64).unwrap(), &config, "");
+    assert_eq!((output.as_str(), stack), ("0", vec![]));
+}
+
+#[cfg(feature = "bignum")]
+#[test]
+fn big_integers_grow_past_64_bits() {
+    let (output, stack) = common::run_with::<rpiet::BigInt>(
+        canvas_from_grid(PRINT_2_POW_64).unwrap(),
+        &common::limited_config(),
+        "",
+    );
+    // 2^64, past i64::MAX
+    assert_eq!((output.as_str(), stack), ("18446744073709551616", vec![]));
+}
+
+// push 2 and square it five times to 2^32, halve a copy and negate it, multiply
+// both to push i64::MIN and push -1, then add, divide or take the modulo
+const MIN_PLUS_MINUS_ONE: &str =
+    "R  R  dR dB M  C  lB lG dC dY G  R  lY lM lM M  Y  dY dY lY G  lC dB lB lB B  dM dR W  W
+     K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  W  W";
+const MIN_DIVIDED_BY_MINUS_ONE: &str =
+    "R  R  dR dB M  C  lB lG dC dY G  R  lY lM lM M  Y  dY dY lY G  lC dB lB lB B  dM dY W  W
+     K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  W  W";
+const MIN_MODULO_MINUS_ONE: &str =
+    "R  R  dR dB M  C  lB lG dC dY G  R  lY lM lM M  Y  dY dY lY G  lC dB lB lB B  dM lY W  W
+     K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  K  W  W";
+// runs the image with an overflow policy, returns the stack and whether it halted on an overflow
+fn run_with_policy(grid: &str, arithmetic_policy: ArithmeticPolicy) -> (Vec<i64>, bool) {
+    let config = Config {
+        arithmetic_policy,
+        ..common::limited_config()
+    };
+    let mut interpreter = common::grid_interpreter(grid, &config);
+    while interpreter.is_alive() {
+        interpreter.advance();
+    }
+    let halted = interpreter.error().is_some();
+    (interpreter.stack().to_vec(), halted)
+}
+
+#[test]
+fn overflow_policies() {
+    let two_pow_32 = 1 << 32;
+    let programs: [(&str, &str, [&[i64]; 3]); 4] = [
+        // expected stacks for ignore (and halt), wrap and saturate
+        (
+            "2^32 * 2^32",
+            common::SQUARE_TO_2_POW_64,
+            [&[two_pow_32, two_pow_32], &[0], &[i64::MAX]],
+        ),
+        (
+            "i64::MIN + -1",
+            MIN_PLUS_MINUS_ONE,
+            [&[i64::MIN, -1], &[i64::MAX], &[i64::MIN]],
+        ),
+        (
+            "i64::MIN / -1",
+            MIN_DIVIDED_BY_MINUS_ONE,
+            [&[i64::MIN, -1], &[i64::MIN], &[i64::MAX]],
+        ),
+        (
+            "i64::MIN mod -1",
+            MIN_MODULO_MINUS_ONE,
+            [&[i64::MIN, -1], &[0], &[0]],
+        ),
+    ];
+    for &(name, grid, [ignored, wrapped, saturated]) in programs.iter() {
+        assert_eq!(
+            run_with_policy(grid, ArithmeticPolicy::Ignore),
+            (ignored.to_vec(), false),
+            "{} ignored",
+            name
+        );
+        assert_eq!(
+            run_with_policy(grid, ArithmeticPolicy::Wrap),
+            (wrapped.to_vec(), false),
+            "{} wrapped",
+            name
+        );
+        assert_eq!(
+            run_with_policy(grid, ArithmeticPolicy::Saturate),
+            (saturated.to_vec(), false),
+            "{} saturated",
+            name
+        );
+        // the operands stay on the stack for inspection
+        assert_eq!(
+            run_with_policy(grid, ArithmeticPolicy::Halt),
+            (ignored.to_vec(), true),
+            "{} halted",
+            name
+        );
+    }
+}
+
+// takes the given number of steps with a compat preset and returns the stack afterwards
+fn stack_after(grid: &str, compat: Compat, steps: usize) -> Vec<i64> {
+    let config = Config {
+        compat,
+        ..Config::default()
+    };
+    let mut interpreter = common::grid_interpreter(grid, &config);
+    for _ in 0..steps {
+        interpreter.advance();
+    }
+    interpreter.stack().to_vec()
+}
+
+#[test]
+fn compat_presets_handle_division_by_zero() {
+    // push 3, push 1, push 1, subtract, divide
+    let divide = "R  R  R  dR lR R  dY dC W  W
+                  K  K  K  K  K  K  K  K  W  W";
+    // push 3, push 1, push 1, subtract, mod
+    let modulo = "R  R  R  dR lR R  dY lC W  W
+                  K  K  K  K  K  K  K  K  W  W";
+    for grid in [divide, modulo].iter() {
+        assert_eq!(stack_after(grid, Compat::Spec, 5), [3, 0]);
+        assert_eq!(stack_after(grid, Compat::Npiet, 5), [3, 0]);
+        assert_eq!(stack_after(grid, Compat::Rpiet, 5), []);
+    }
+}
+
+#[test]
+fn compat_presets_handle_a_negative_roll_depth() {
+    // push 1, push 2, subtract, push 1, roll
+    let grid = "R  dR dR lR Y  dY lM W  W
+                K  K  K  K  K  K  K  W  W";
+    assert_eq!(stack_after(grid, Compat::Spec, 5), [-1, 1]);
+    assert_eq!(stack_after(grid, Compat::Npiet, 5), []);
+    assert_eq!(stack_after(grid, Compat::Rpiet, 5), [-1, 1]);
+}
+
+#[test]
+fn compat_presets_handle_mod_with_a_negative_divisor() {
+    // push 5, push 1, push 4, subtract, mod
+    let grid = "R  R  R  R  R  dR lR lR lR lR R  dY lC W  W
+                K  K  K  K  K  K  K  K  K  K  K  K  K  W  W";
+    assert_eq!(stack_after(grid, Compat::Spec, 5), [-1]);
+    assert_eq!(stack_after(grid, Compat::Npiet, 5), [-1]);
+    assert_eq!(stack_after(grid, Compat::Rpiet, 5), [2]);
+}
+
+// takes the given number of steps with a compat preset and returns DP and CC afterwards
+fn pointers_with(grid: &str, compat: Compat, steps: usize) -> (DirectionPointer, CodelChooser) {
+    let config = Config {
+        compat,
+        ..Config::default()
+    };
+    let mut interpreter = common::grid_interpreter(grid, &config);
+    for _ in 0..steps {
+        interpreter.advance();
+    }
+    (interpreter.dp(), interpreter.cc())
+}
+
+#[test]
+fn compat_presets_handle_switch_with_a_negative_odd_value() {
+    // push 1, push 2, subtract, switch with -1
+    let grid = "R  dR dR lR Y  lB W  W
+                K  K  K  K  K  K  W  W";
+    let toggled = (DirectionPointer::Right, CodelChooser::Right);
+    assert_eq!(pointers_with(grid, Compat::Spec, 4), toggled);
+    assert_eq!(pointers_with(grid, Compat::Npiet, 4), toggled);
+    assert_eq!(
+        pointers_with(grid, Compat::Rpiet, 4),
+        (DirectionPointer::Right, CodelChooser::Left)
+    );
+}
+
+#[test]
+fn compat_presets_handle_toggling_after_a_move() {
+    // toggling CC leads from the red block to green, which is blocked again
+    let grid = "R  K
+                R  G";
+    let toggled_cc = (DirectionPointer::Right, CodelChooser::Left);
+    assert_eq!(pointers_with(grid, Compat::Spec, 3), toggled_cc);
+    assert_eq!(pointers_with(grid, Compat::Npiet, 3), toggled_cc);
+    // rpiet <= 0.3 went on with turning DP
+    assert_eq!(
+        pointers_with(grid, Compat::Rpiet, 3),
+        (DirectionPointer::Down, CodelChooser::Right)
+    );
+}
+
+#[test]
+fn rpiet_compat_stops_slides_at_the_first_obstacle() {
+    let config = Config {
+        compat: Compat::Rpiet,
+        ..Config::default()
+    };
+    let grid = "R  W  W
+                K  W  W";
+    let mut interpreter = common::grid_interpreter(grid, &config);
+    // the slide stops at the edge and toggles CC like a blocked color block would
+    interpreter.advance();
+    assert!(interpreter.is_alive());
+    assert_eq!(interpreter.current_position(), (2, 0));
+    assert_eq!(
+        (interpreter.dp(), interpreter.cc()),
+        (DirectionPointer::Right, CodelChooser::Right)
+    );
+    // and continues from the white codel after the next toggle
+    interpreter.advance();
+    interpreter.advance();
+    assert_eq!(interpreter.current_position(), (2, 1));
+    assert_eq!(interpreter.dp(), DirectionPointer::Down);
+}
+
+#[test]
+fn npiet_compat_slides_like_spec() {
+    let grids = [
+        // trapped right away
+        "R  W  W
+         K  W  W",
+        // turns down at the black codel and enters the light yellow block, then keeps going around
+        "R  R  R  R  R  R  R  dR W  K
+         K  K  K  K  K  K  K  K  W  K
+         K  K  K  K  K  K  K  K  lY K
+         K  K  K  K  K  K  K  K  R  K",
+    ];
+    for grid in grids.iter() {
+        let spec = Config {
+            compat: Compat::Spec,
+            ..Config::default()
+        };
+        let npiet = Config {
+            compat: Compat::Npiet,
+            ..Config::default()
+        };
+        let mut spec = common::grid_interpreter(grid, &spec);
+        let mut npiet = common::grid_interpreter(grid, &npiet);
+        for _ in 0..100 {
+            spec.advance();
+            npiet.advance();
+            assert_eq!(
+                (npiet.current_position(), npiet.dp(), npiet.cc()),
+                (spec.current_position(), spec.dp(), spec.cc()),
+                "{}",
+                grid
+            );
+            assert_eq!(npiet.is_alive(), spec.is_alive(), "{}", grid);
+            if !spec.is_alive() {
+                break;
+            }
+        }
+    }
+}
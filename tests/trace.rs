@@ -0,0 +1,65 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use rpiet::{canvas_from_grid, write_png, CodelSize, Config};
+
+use common::TempFile;
+
+// push 2, a slide through white, three toggles until the blue block finds a way down, duplicate
+const GRID: &str = "R  R  dR W  B  B  K
+                    K  K  K  K  K  G  K";
+
+// runs the rpiet binary on `GRID` for seven steps with the given option, which writes to `file`
+fn trace_grid(option: &str, file: &TempFile) {
+    let image = TempFile::new(&format!("trace{}.png", option));
+    write_png(&canvas_from_grid(GRID).unwrap(), 1, image.create()).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rpiet"))
+        .args(["--max-steps", "7", option])
+        .arg(file.path())
+        .arg(image.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", option);
+}
+
+#[test]
+fn trace_writes_one_json_object_per_step() {
+    let trace = TempFile::new("trace.jsonl");
+    trace_grid("--trace", &trace);
+
+    let expected = [
+        r#"{"step":1,"old_position":[0,0],"new_position":[2,0],"dp":"right","cc":"left","event":"command","command":"PUSH","block_size":2,"stack":[2]}"#,
+        r#"{"step":2,"old_position":[2,0],"new_position":[4,0],"dp":"right","cc":"left","event":"slide","block_size":1,"stack":[2]}"#,
+        r#"{"step":3,"old_position":[4,0],"new_position":[4,0],"dp":"right","cc":"right","event":"toggle","block_size":2,"stack":[2]}"#,
+        r#"{"step":4,"old_position":[4,0],"new_position":[4,0],"dp":"down","cc":"right","event":"toggle","block_size":2,"stack":[2]}"#,
+        r#"{"step":5,"old_position":[4,0],"new_position":[4,0],"dp":"down","cc":"left","event":"toggle","block_size":2,"stack":[2]}"#,
+        r#"{"step":6,"old_position":[4,0],"new_position":[5,1],"dp":"down","cc":"left","event":"command","command":"DUPLICATE","block_size":2,"stack":[2,2]}"#,
+        r#"{"step":7,"old_position":[5,1],"new_position":[5,1],"dp":"down","cc":"left","event":"exit","block_size":1,"stack":[2,2]}"#,
+    ];
+    let lines = fs::read_to_string(trace.path()).unwrap();
+    assert_eq!(lines.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn render_trace_draws_the_path_on_a_scaled_up_image() {
+    let rendered = TempFile::new("render-trace.png");
+    trace_grid("--render-trace", &rendered);
+    let config = Config {
+        codel_size: CodelSize::Fixed(1),
+        ..Config::default()
+    };
+    let pixels = rendered.load_canvas(&config).unwrap();
+    // 7 x 2 codels are scaled up 24 times
+    assert_eq!((pixels[0].len(), pixels.len()), (168, 48));
+    let center = |(x, y): (usize, usize)| pixels[y * 24 + 12][x * 24 + 12];
+    // the corner of the red block, which the path does not cross
+    assert_eq!(pixels[3][3], (0xFF, 0x00, 0x00));
+    // the arrow of PUSH crosses the second red codel
+    assert_eq!(center((1, 0)), (0x00, 0x00, 0x00));
+    // the dashed arrow of the slide crosses the white codel
+    assert_eq!(center((3, 0)), (0x80, 0x80, 0x80));
+    // the blue block toggled
+    assert_eq!(center((4, 0)), (0xFF, 0x80, 0x00));
+}